@@ -0,0 +1,121 @@
+//! Permissions of implicit drops at scope exit.
+//!
+//! Values still owned at the end of their scope are dropped at the closing
+//! brace, which requires the Own permission. A drop is blocked if a borrow
+//! of the value is still live, e.g., when the borrow is held by a value with
+//! a `Drop` impl which is dropped later. These drops have no HIR node, so
+//! their boundaries are found directly from the `Drop` terminators in MIR,
+//! keeping those at the end of the source scope of the dropped local.
+
+use fluid_let::fluid_let;
+use rustc_data_structures::fx::FxHashSet as HashSet;
+use rustc_middle::mir::TerminatorKind;
+use rustc_utils::{source_map::range::ByteRange, PlaceExt, SpanExt};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::PermissionsBoundary;
+use crate::analysis::{permissions::Permissions, AquascopeAnalysis};
+
+fluid_let!(pub static ENABLE_IMPLICIT_DROPS: bool);
+
+/// How a value is dropped at the end of its scope.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub enum ImplicitDrop {
+  /// The type implements `Drop`, which may use the borrows it holds.
+  Custom,
+  /// Only the drop glue runs, dropping the fields of the value.
+  Glue,
+}
+
+const EXPECTED: Permissions = Permissions {
+  read: false,
+  write: false,
+  drop: true,
+  interior_write: false,
+};
+
+/// Find the boundaries of values dropped at the closing brace of their
+/// scope, if [`ENABLE_IMPLICIT_DROPS`] is set.
+pub(super) fn get_drop_boundaries(
+  analysis: &AquascopeAnalysis<'_>,
+) -> Vec<PermissionsBoundary> {
+  if !ENABLE_IMPLICIT_DROPS.copied().unwrap_or(false) {
+    return Vec::new();
+  }
+
+  let ctxt = &analysis.permissions;
+  let tcx = ctxt.tcx;
+  let body = &ctxt.body_with_facts.body;
+  let source_map = tcx.sess.source_map();
+
+  let mut seen = HashSet::default();
+  let mut boundaries = Vec::new();
+
+  for (block, data) in body.basic_blocks.iter_enumerated() {
+    if data.is_cleanup {
+      continue;
+    }
+    let terminator = data.terminator();
+    let TerminatorKind::Drop { place, .. } = terminator.kind else {
+      continue;
+    };
+    if !place.is_source_visible(tcx, body) {
+      continue;
+    }
+
+    // Drops at scope exit are spanned to the closing brace of the scope,
+    // which is also the end of the scope the local is declared in. Others,
+    // e.g. of an overwritten value, have a source-level counterpart which
+    // already has a boundary.
+    let decl = &body.local_decls[place.local];
+    let scope_span = body.source_scopes[decl.source_info.scope].span;
+    let span = terminator.source_info.span;
+    if span != source_map.end_point(scope_span) {
+      continue;
+    }
+    let Some(span) = span.as_local(body.span) else {
+      continue;
+    };
+
+    let point = ctxt.location_to_point(body.terminator_loc(block));
+    let Some(path) = ctxt.try_place_to_path(&place) else {
+      continue;
+    };
+    let data = ctxt.permissions_data_at_point(path, point);
+
+    // A moved-out value is not dropped.
+    if data.path_moved.is_some() || data.path_uninitialized {
+      continue;
+    }
+
+    // A value may be dropped on several paths out of the same scope.
+    if !seen.insert((span.lo(), path)) {
+      continue;
+    }
+
+    let ty = place.ty(body, tcx).ty;
+    let kind = match ty.ty_adt_def() {
+      Some(adt) if adt.has_dtor(tcx) => ImplicitDrop::Custom,
+      _ => ImplicitDrop::Glue,
+    };
+
+    let span = span.shrink_to_lo();
+    boundaries.push(PermissionsBoundary {
+      location: analysis.span_to_range(span).end,
+      byte_location: ByteRange::from_span(span, source_map).unwrap().end,
+      expected: EXPECTED,
+      actual: data.permissions_ignore_liveness(),
+      data,
+      expecting_flow: None,
+      unchecked: None,
+      implicit_drop: Some(kind),
+      operator: None,
+      desugaring: None,
+      in_macro: None,
+    });
+  }
+
+  boundaries
+}
@@ -75,6 +75,9 @@
 //! available in the HIR. For this macro, if you just look at the source location it will point
 //! to somewhere from within rustc. We utilize the [`SpanExt::as_local`] method to sanitize spans
 //! and lift them back to original source code.
+//! Uses inside of user-defined `macro_rules!` macros are filtered out the same way, unless
+//! [`ENABLE_MACRO_TRACING`] is set. Then the boundary is placed on the tokens the caller
+//! passed to the macro, and marked with the name of the macro.
 //! Lastly, the struct [`ExpectedPermissions`] has a series of construction methods
 //! which show concisely when certain permissions are expected for the respective uses.
 //! In this case, a shared borrow only requires the Read permission.
@@ -173,53 +176,74 @@
 //! The entry location to this process of resolving a HIR path to a MIR place,
 //! and retrieving the permissions can be found in the [`path_to_perm_boundary`] function.
 
+mod drops;
 pub(crate) mod path_visitor;
+mod unchecked;
 
 use anyhow::Result;
+pub use drops::{ImplicitDrop, ENABLE_IMPLICIT_DROPS};
 use either::Either;
 use path_visitor::get_path_boundaries;
-use rustc_hir::HirId;
+pub use path_visitor::ENABLE_MACRO_TRACING;
+use rustc_borrowck::consumers::PoloniusRegionVid;
+use rustc_data_structures::fx::FxHashMap as HashMap;
+use rustc_hir::{HirId, Node, Pat, PatKind};
 use rustc_middle::{
   mir::{
     Body, Location, Mutability, Operand, Place, Rvalue, Statement,
-    StatementKind,
+    StatementKind, RETURN_PLACE,
   },
-  ty::{adjustment::AutoBorrowMutability, TyCtxt},
+  ty::{self, adjustment::AutoBorrowMutability, Ty, TyCtxt},
 };
 use rustc_span::Span;
 use rustc_utils::{
   source_map::range::{BytePos, ByteRange, CharPos, CharRange},
   OperandExt, PlaceExt, SpanExt,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use smallvec::{smallvec, SmallVec};
 use ts_rs::TS;
+pub use unchecked::{AliasedPlace, UncheckedAccess};
 
 use crate::{
   analysis::{
     ir_mapper::{GatherDepth, IRMapper},
     permissions::{
-      flow::FlowEdgeKind, Origin, Permissions, PermissionsCtxt,
+      flow::FlowEdgeKind, Origin, Path, Permissions, PermissionsCtxt,
       PermissionsData, Point, ENABLE_FLOW_DEFAULT, ENABLE_FLOW_PERMISSIONS,
     },
-    AquascopeAnalysis,
+    AquascopeAnalysis, LoanKey,
   },
   errors,
 };
 
 /// A point where a region flow is introduced, potentially resulting in a violation.
-#[derive(Debug, Clone, Serialize, TS)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
 pub struct FlowBoundary {
   // Used for simplicity in the frontend, later the extra information
   // in the flow kind can be shown with extra details.
-  is_violation: bool,
-  flow_context: CharRange,
-  kind: FlowEdgeKind,
+  pub is_violation: bool,
+  pub flow_context: CharRange,
+  pub kind: FlowEdgeKind,
+  /// Where a local goes out of scope while borrowed, for
+  /// [`FlowEdgeKind::LocalOutlivesLocal`] violations.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub scope_violation: Option<ScopeViolation>,
+}
+
+/// A borrow of a local which is used after the local goes out of scope.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ScopeViolation {
+  pub borrow: CharRange,
+  pub scope_end: CharRange,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub later_use: Option<CharRange>,
 }
 
 /// A point where the permissions reality are checked against their expectations.
-#[derive(Debug, Clone, Serialize, TS)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
 pub struct PermissionsBoundary {
   pub location: CharPos,
@@ -230,6 +254,47 @@ pub struct PermissionsBoundary {
   pub data: PermissionsData,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub expecting_flow: Option<FlowBoundary>,
+  /// Set if the path is reached through a raw pointer, and therefore
+  /// not checked by the borrow checker.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub unchecked: Option<UncheckedAccess>,
+  /// Set if the path is implicitly dropped at the end of its scope.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub implicit_drop: Option<ImplicitDrop>,
+  /// Set if the path is implicitly borrowed by an overloaded operator.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub operator: Option<OverloadedOperator>,
+  /// Set if the path is the operand of a `for` loop, `?` or `.await`.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub desugaring: Option<Desugaring>,
+  /// The name of the user macro the path is used in, if the use was
+  /// traced through its expansion.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub in_macro: Option<String>,
+}
+
+/// Expressions desugared into a call on their operand, which moves or
+/// borrows it without the call appearing in the source.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub enum Desugaring {
+  /// `for x in v`, calling `IntoIterator::into_iter(v)`.
+  ForLoop,
+  /// `v?`, calling `Try::branch(v)`.
+  Try,
+  /// `v.await`, calling `IntoFuture::into_future(v)`.
+  Await,
+}
+
+/// An operator resolved to a trait method which borrows its operand,
+/// e.g., `v[i]` calling `Index::index(&v, i)`.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OverloadedOperator {
+  /// The trait of the operator, e.g., `IndexMut` or `AddAssign`.
+  pub trait_name: String,
+  /// Is the operand borrowed mutably?
+  pub mutable: bool,
 }
 
 impl PermissionsBoundary {
@@ -243,6 +308,7 @@ impl PermissionsBoundary {
     is_missing!(self, read)
       || is_missing!(self, write)
       || is_missing!(self, drop)
+      || is_missing!(self, interior_write)
   }
 }
 
@@ -258,6 +324,7 @@ impl ExpectedPermissions {
       read: true,
       write: true,
       drop: false,
+      interior_write: false,
     })
   }
 
@@ -266,6 +333,7 @@ impl ExpectedPermissions {
       read: true,
       write: matches!(mutability, Mutability::Mut),
       drop: false,
+      interior_write: false,
     })
   }
 
@@ -274,6 +342,7 @@ impl ExpectedPermissions {
       read: true,
       write: matches!(mutability, AutoBorrowMutability::Mut { .. }),
       drop: false,
+      interior_write: false,
     })
   }
 
@@ -282,6 +351,7 @@ impl ExpectedPermissions {
       read: true,
       write: false,
       drop: true,
+      interior_write: false,
     })
   }
 
@@ -290,6 +360,7 @@ impl ExpectedPermissions {
       read: true,
       write: false,
       drop: false,
+      interior_write: false,
     })
   }
 
@@ -298,6 +369,16 @@ impl ExpectedPermissions {
       read: true,
       write: false,
       drop: false,
+      interior_write: false,
+    })
+  }
+
+  /// Expect the path to be written through a shared reference,
+  /// e.g., the receiver of `Cell::set`.
+  pub fn with_interior_write(self) -> Self {
+    Self(Permissions {
+      interior_write: true,
+      ..self.0
     })
   }
 }
@@ -328,6 +409,12 @@ struct PathBoundary {
 
   /// The permissions required for the [`Place`] usage.
   pub expected: ExpectedPermissions,
+
+  /// Set if the path is implicitly borrowed by an overloaded operator.
+  pub operator: Option<OverloadedOperator>,
+
+  /// Set if the path is the operand of a desugared expression.
+  pub desugaring: Option<Desugaring>,
 }
 
 impl std::fmt::Debug for PathBoundary {
@@ -501,16 +588,30 @@ fn get_flow_permission(
       .any(|&(_, t, _)| region_flows.is_abstract_member(t))
   };
 
+  // Do any given constraints flow a local into a region outliving it?
+  let has_local_outlives_local = |flows: &[(Origin, Origin, Point)]| {
+    flows.iter().any(|&(f, t, _)| {
+      matches!(
+        region_flows.flow_kind(f, t),
+        FlowEdgeKind::LocalOutlivesLocal
+      )
+    })
+  };
+
   let context_constraints =
     flow_constraints_at_hir_id(ctxt, ir_mapper, flow_context)?;
 
   // FIXME: current restriction, only look at constraints when
-  // an abstract equivalent region is on the right-hand-side.
+  // an abstract equivalent region is on the right-hand-side,
+  // or a local outlives another local.
   //
   // This covers the cases:
   // - missing abstract-outlives-abstract constraint.
   // - local outlives abstract.
-  if !has_abstract_on_rhs(&context_constraints) {
+  // - local outlives local.
+  if !has_abstract_on_rhs(&context_constraints)
+    && !has_local_outlives_local(&context_constraints)
+  {
     return None;
   }
 
@@ -539,17 +640,19 @@ fn get_flow_permission(
       let fk = region_flows.flow_kind(from, to);
 
       // We want to look specifically for flows that:
-      // - flow to an abstract region (XXX: a current design constraint to be lifter)
+      // - flow to an abstract region (XXX: a current design constraint to be lifter),
+      //   or flow a local into a region outliving it
       // - are invalid
       // - the local constraints create a context constraint involved in the violation.
-      if region_flows.is_abstract_member(to)
+      if (region_flows.is_abstract_member(to)
+        || matches!(fk, FlowEdgeKind::LocalOutlivesLocal))
         && !fk.is_valid_flow()
         && specific_constraints
           .iter()
           .any(|&(_f, t, _)| t == from || t == to)
       {
         log::debug!("found flow violation: {fk:?} @ {from:?} -> {to:?}");
-        Some(fk)
+        Some((fk, from))
       } else {
         None
       }
@@ -561,11 +664,19 @@ fn get_flow_permission(
   //
   // A brief discussion at:
   // https://github.com/cognitive-engineering-lab/aquascope/pull/51#discussion_r1141095658
-  let kind = flow_violations.next().unwrap_or_else(|| {
+  let (kind, from) = flow_violations.next().unzip();
+  let kind = kind.unwrap_or_else(|| {
     log::debug!("No flow edge violation found");
     FlowEdgeKind::Ok
   });
 
+  let scope_violation = match (&kind, from) {
+    (FlowEdgeKind::LocalOutlivesLocal, Some(from)) => {
+      get_scope_violation(analysis, from)
+    }
+    _ => None,
+  };
+
   let raw_span = hir.span(flow_context);
   let span = raw_span.as_local(body.span).unwrap_or(body.span);
   let flow_context = analysis.span_to_range(span);
@@ -574,6 +685,194 @@ fn get_flow_permission(
     is_violation: !kind.is_valid_flow(),
     flow_context,
     kind,
+    scope_violation,
+  })
+}
+
+/// Find where the local flowing from `origin` is borrowed, goes out of
+/// scope, and is later used.
+fn get_scope_violation(
+  analysis: &AquascopeAnalysis,
+  origin: Origin,
+) -> Option<ScopeViolation> {
+  let ctxt = &analysis.permissions;
+  let body = &ctxt.body_with_facts.body;
+  let (loan, point) = ctxt.region_flows().local_scope_end(origin)?;
+
+  let location_to_range = |location: Location| {
+    let span = ctxt.location_to_span(location);
+    analysis.span_to_range(span.as_local(body.span).unwrap_or(span))
+  };
+
+  let scope_end = ctxt.point_to_location(point);
+  let later_use = analysis
+    .explain_loan(LoanKey(loan.as_u32()), scope_end)
+    .and_then(|explanation| explanation.later_use);
+
+  Some(ScopeViolation {
+    borrow: location_to_range(ctxt.loan_to_borrow(loan).reserve_location()),
+    scope_end: location_to_range(scope_end),
+    later_use,
+  })
+}
+
+/// Hidden type capture violations of a body, keyed by the location and place
+/// of the path flowing into the return place.
+type HiddenTypeCaptures<'tcx> = HashMap<(Location, Place<'tcx>), FlowEdgeKind>;
+
+/// If flow permissions are enabled, find the hidden type capture violations
+/// at the return sites of an `impl Trait` returning body, i.e., at the
+/// assignments to its return place.
+///
+/// Hidden type errors stem from member constraints, which don't show up
+/// in the `subset_base` constraints checked by [`get_flow_permission`].
+fn get_hidden_type_captures<'tcx>(
+  ctxt: &PermissionsCtxt<'tcx>,
+) -> HiddenTypeCaptures<'tcx> {
+  let mut captures = HashMap::default();
+  if !ENABLE_FLOW_PERMISSIONS
+    .copied()
+    .unwrap_or(ENABLE_FLOW_DEFAULT)
+  {
+    return captures;
+  }
+
+  let tcx = ctxt.tcx;
+  let body = &ctxt.body_with_facts.body;
+  let region_flows = ctxt.region_flows();
+
+  let origins_of = |ty: Ty| {
+    ty.walk()
+      .filter_map(|arg| arg.as_region())
+      .filter(|region| region.is_var())
+      .map(|region| PoloniusRegionVid::from(region.as_var()))
+      .collect::<Vec<_>>()
+  };
+
+  // Opaque types capture the arguments which aren't bivariant.
+  let opaques = body.local_decls[RETURN_PLACE]
+    .ty
+    .walk()
+    .filter_map(|arg| arg.as_type())
+    .filter_map(|ty| match ty.kind() {
+      ty::Alias(ty::AliasTyKind::Opaque, alias) => Some(*alias),
+      _ => None,
+    })
+    .collect::<Vec<_>>();
+  if opaques.is_empty() {
+    return captures;
+  }
+  let captured = opaques
+    .iter()
+    .flat_map(|alias| {
+      let variances = tcx.variances_of(alias.def_id);
+      alias
+        .args
+        .iter()
+        .zip(variances)
+        .filter(|(_, variance)| **variance != ty::Variance::Bivariant)
+        .filter_map(|(arg, _)| arg.as_region())
+    })
+    .filter(|region| region.is_var())
+    .map(|region| PoloniusRegionVid::from(region.as_var()))
+    .collect::<Vec<_>>();
+
+  for (block, data) in body.basic_blocks.iter_enumerated() {
+    for (statement_index, stmt) in data.statements.iter().enumerate() {
+      let StatementKind::Assign(box (place, rvalue)) = &stmt.kind else {
+        continue;
+      };
+      if place.local != RETURN_PLACE || !place.projection.is_empty() {
+        continue;
+      }
+
+      let kind = region_flows
+        .hidden_type_flow_kind(&origins_of(rvalue.ty(body, tcx)), &captured);
+      if kind.is_valid_flow() {
+        continue;
+      }
+
+      let location = Location {
+        block,
+        statement_index,
+      };
+      if let Some(returned) = returned_place(tcx, body, location, rvalue) {
+        log::debug!("found hidden type violation: {kind:?} @ {returned:?}");
+        captures.insert(returned, kind);
+      }
+    }
+  }
+
+  captures
+}
+
+/// The source-level place returned by the assignment of `rvalue` to the
+/// return place at `location`, with the location it is used at. Temporaries
+/// the place is moved through, e.g. `_2 = &(*_1); _0 = move _2`, are
+/// followed back to the source-level place.
+fn returned_place<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  body: &Body<'tcx>,
+  location: Location,
+  rvalue: &Rvalue<'tcx>,
+) -> Option<(Location, Place<'tcx>)> {
+  let used_place = |rvalue: &Rvalue<'tcx>| match rvalue {
+    Rvalue::Use(op) | Rvalue::Cast(_, op, _) => op.place(),
+    Rvalue::Ref(_, _, place)
+    | Rvalue::RawPtr(_, place)
+    | Rvalue::CopyForDeref(place) => Some(*place),
+    _ => None,
+  };
+
+  let mut location = location;
+  let mut place = used_place(rvalue)?;
+  while !place.is_source_visible(tcx, body) {
+    let local = place.as_local()?;
+    (location, place) =
+      body
+        .basic_blocks
+        .iter_enumerated()
+        .find_map(|(block, data)| {
+          data.statements.iter().enumerate().find_map(|(i, stmt)| {
+            let StatementKind::Assign(box (lhs, rvalue)) = &stmt.kind else {
+              return None;
+            };
+            if lhs.as_local() != Some(local) {
+              return None;
+            }
+            let location = Location {
+              block,
+              statement_index: i,
+            };
+            used_place(rvalue).map(|place| (location, place))
+          })
+        })?;
+  }
+
+  Some((location, place))
+}
+
+/// The hidden type capture violation of `path` used at `point`, if `path`
+/// is the place flowing into the return place there.
+fn get_hidden_type_capture(
+  analysis: &AquascopeAnalysis,
+  captures: &HiddenTypeCaptures,
+  flow_context: HirId,
+  point: Point,
+  path: Path,
+) -> Option<FlowBoundary> {
+  let ctxt = &analysis.permissions;
+  let body = &ctxt.body_with_facts.body;
+  let key = (ctxt.point_to_location(point), ctxt.path_to_place(path));
+  let kind = *captures.get(&key)?;
+
+  let span = ctxt.tcx.hir().span(flow_context);
+  let span = span.as_local(body.span).unwrap_or(body.span);
+  Some(FlowBoundary {
+    is_violation: true,
+    flow_context: analysis.span_to_range(span),
+    kind,
+    scope_violation: None,
   })
 }
 
@@ -715,9 +1014,10 @@ fn paths_at_hir_id<'tcx>(
   Some(mir_locations)
 }
 
-fn path_to_perm_boundary(
+fn path_to_perm_boundary<'tcx>(
   path_boundary: PathBoundary,
-  analysis: &AquascopeAnalysis<'_>,
+  analysis: &AquascopeAnalysis<'tcx>,
+  captures: &HiddenTypeCaptures<'tcx>,
 ) -> Option<PermissionsBoundary> {
   let ctxt = &analysis.permissions;
   let ir_mapper = &analysis.ir_mapper;
@@ -756,50 +1056,90 @@ fn path_to_perm_boundary(
     Some((point, path))
   };
 
-  // For a given Path, the MIR location may not be immediately associated with it.
-  // For example, in a function call `foo( &x );`, the Hir Node::Path `&x` will not
-  // have the MIR locations associated with it, the Hir Node::Call `foo( &x )` will,
-  // so we traverse upwards in the tree until we find a location associated with it.
-  let resolved_boundary = search_at_hir_id(hir_id)
-    .or_else(|| {
+  let is_binding = matches!(
+    tcx.hir_node(hir_id),
+    Node::Pat(Pat {
+      kind: PatKind::Binding(..),
+      ..
+    })
+  );
+
+  let resolved_path = if is_binding {
+    // A binding is resolved to the place it matches, the binding's own
+    // local is still uninitialized at this point.
+    binding_scrutinee(body, ir_mapper, hir_id).and_then(|(loc, place)| {
+      let path = ctxt.try_place_to_path(&place)?;
+      Some((ctxt.location_to_point(loc), path))
+    })
+  } else {
+    // For a given Path, the MIR location may not be immediately associated with it.
+    // For example, in a function call `foo( &x );`, the Hir Node::Path `&x` will not
+    // have the MIR locations associated with it, the Hir Node::Call `foo( &x )` will,
+    // so we traverse upwards in the tree until we find a location associated with it.
+    search_at_hir_id(hir_id).or_else(|| {
       hir.parent_iter(hir_id).find_map(|(hir_id, _)| {
         log::debug!("\tsearching upwards in: {}", hir.node_to_string(hir_id));
         search_at_hir_id(hir_id)
       })
     })
-    .map(|(point, path)| {
-      let data = ctxt.permissions_data_at_point(path, point);
-      let expected = path_boundary.expected;
-      let actual = data.permissions_ignore_liveness();
-
-      let expecting_flow =
-        get_flow_permission(analysis, path_boundary.flow_context, hir_id);
-
-      log::debug!("Permissions data for {}:\n{actual:#?}\n{expected:#?}\n{expecting_flow:#?}", hir.node_to_string(path_boundary.hir_id));
-
-      let span = path_boundary
-        .location
-        .as_local(body.span)
-        .unwrap_or(path_boundary.location);
-
-      // FIXME(gavinleroy): the spans are chosen in the `path_visitor` such that the end
-      // of the span is where we want the stack to be placed. I would like to
-      // make this a bit more explicit.
-      let location = analysis.span_to_range(span).end;
-      let byte_location = ByteRange::from_span(span, tcx.sess.source_map())
-        .unwrap()
-        .end;
-
-      PermissionsBoundary {
-        location,
-        byte_location,
-        expected: expected.into(),
-        actual,
-        data,
-        expecting_flow,
-      }
+  };
+
+  let resolved_boundary = resolved_path.map(|(point, path)| {
+    let data = ctxt.permissions_data_at_point(path, point);
+    let expected = path_boundary.expected;
+    let actual = data.permissions_ignore_liveness();
+
+    let expecting_flow = get_hidden_type_capture(
+      analysis,
+      captures,
+      path_boundary.flow_context,
+      point,
+      path,
+    )
+    .or_else(|| {
+      get_flow_permission(analysis, path_boundary.flow_context, hir_id)
     });
 
+    let in_macro = path_visitor::macro_of_use(tcx, hir_id)
+      .map(|name| name.to_string());
+
+    let unchecked = unchecked::get_unchecked_access(
+      analysis,
+      ctxt.path_to_place(path),
+      point,
+      expected.into(),
+    );
+
+    log::debug!("Permissions data for {}:\n{actual:#?}\n{expected:#?}\n{expecting_flow:#?}", hir.node_to_string(path_boundary.hir_id));
+
+    let span = path_boundary
+      .location
+      .as_local(body.span)
+      .unwrap_or(path_boundary.location);
+
+    // FIXME(gavinleroy): the spans are chosen in the `path_visitor` such that the end
+    // of the span is where we want the stack to be placed. I would like to
+    // make this a bit more explicit.
+    let location = analysis.span_to_range(span).end;
+    let byte_location = ByteRange::from_span(span, tcx.sess.source_map())
+      .unwrap()
+      .end;
+
+    PermissionsBoundary {
+      location,
+      byte_location,
+      expected: expected.into(),
+      actual,
+      data,
+      expecting_flow,
+      unchecked,
+      implicit_drop: None,
+      operator: path_boundary.operator.clone(),
+      desugaring: path_boundary.desugaring,
+      in_macro,
+    }
+  });
+
   if resolved_boundary.is_none() {
     log::warn!(
       "Could not resolve a MIR place for expected boundary {}",
@@ -810,27 +1150,59 @@ fn path_to_perm_boundary(
   resolved_boundary
 }
 
+/// The place moved, copied or borrowed into the local of the binding at
+/// `hir_id`, e.g., `(_1 as Some).0` for the `s` of `Some(s)`.
+fn binding_scrutinee<'tcx>(
+  body: &Body<'tcx>,
+  ir_mapper: &IRMapper<'tcx>,
+  hir_id: HirId,
+) -> Option<(Location, Place<'tcx>)> {
+  let locations = ir_mapper.get_mir_locations(hir_id, GatherDepth::Nested)?;
+  locations.values().find_map(|loc| {
+    let Either::Left(Statement {
+      kind: StatementKind::Assign(box (lhs, rvalue)),
+      ..
+    }) = body.stmt_at(loc)
+    else {
+      return None;
+    };
+    let local = lhs.as_local()?;
+    if !body.local_decls[local].is_user_variable() {
+      return None;
+    }
+    match rvalue {
+      Rvalue::Use(op) => op.place().map(|place| (loc, place)),
+      Rvalue::Ref(_, _, place) => Some((loc, *place)),
+      _ => None,
+    }
+  })
+}
+
 #[allow(clippy::module_name_repetitions)]
 pub fn compute_permission_boundaries(
   analysis: &AquascopeAnalysis<'_>,
 ) -> Result<Vec<PermissionsBoundary>> {
   let ctxt = &analysis.permissions;
 
+  let captures = get_hidden_type_captures(ctxt);
   let path_use_points = get_path_boundaries(ctxt)?
     .into_iter()
-    .filter_map(|pb| path_to_perm_boundary(pb, analysis));
+    .filter_map(|pb| path_to_perm_boundary(pb, analysis, &captures))
+    .chain(drops::get_drop_boundaries(analysis));
 
   // FIXME: we need a more robust way of filtering by "first error".
   // here (and in the stepper) we do this by diagnostic span from rustc
   // but that can sometimes be a little earlier than we might want.
   let first_error_span_opt =
-    errors::get_span_of_first_error(ctxt.def_id.expect_local())
+    errors::get_span_of_first_error(ctxt.tcx, ctxt.def_id.expect_local())
       .and_then(|s| s.as_local(ctxt.body_with_facts.body.span));
 
   let boundaries = path_use_points
     .filter(|pb| {
+      // Errors caused by a drop, e.g. "borrowed value does not live long
+      // enough", are reported at an earlier borrow, so drops are kept.
       first_error_span_opt.is_none_or(|error_span| {
-        pb.expecting_flow.is_some() || {
+        pb.expecting_flow.is_some() || pb.implicit_drop.is_some() || {
           let error_range =
             ByteRange::from_span(error_span, ctxt.tcx.sess.source_map())
               .unwrap();
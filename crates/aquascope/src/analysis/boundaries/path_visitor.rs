@@ -1,28 +1,99 @@
 //! Visitor to calculate expected permissions for path usages.
 
-use anyhow::{bail, Result};
+use anyhow::Result;
 use fluid_let::{fluid_let, fluid_set};
 use rustc_hir::{
   def::Res,
   intravisit::{self, Visitor},
-  Block, Body, Expr, ExprKind, HirId, Path, QPath, Stmt, UnOp,
+  Arm, BindingMode, Block, Body, ByRef, Expr, ExprKind, HirId, LetExpr,
+  LetStmt, MatchSource, Node, Pat, PatKind, Path, QPath, Stmt, UnOp,
 };
 use rustc_middle::{
   hir::nested_filter::OnlyBodies,
+  mir::Mutability,
   ty::{
     adjustment::{Adjust, AutoBorrow},
     TyCtxt, TypeckResults, TypingEnv,
   },
 };
-use rustc_span::Span;
-use rustc_utils::TyExt;
+use rustc_span::{
+  hygiene::{ExpnKind, MacroKind},
+  Span, Symbol,
+};
+use rustc_utils::{source_map::range::CharRange, TyExt};
 
-use super::{ExpectedPermissions, PathBoundary};
-use crate::analysis::permissions::PermissionsCtxt;
+use super::{
+  Desugaring, ExpectedPermissions, OverloadedOperator, PathBoundary,
+};
+use crate::analysis::{
+  permissions::{
+    PermissionsCtxt, ENABLE_INTERIOR_DEFAULT, ENABLE_INTERIOR_MUTABILITY,
+  },
+  AquascopeError,
+};
 
 // The current region flow context for outer statements and returns.
 fluid_let!(pub static FLOW_CONTEXT: HirId);
 
+// Trace path uses through the expansions of user-defined macros.
+fluid_let!(pub static ENABLE_MACRO_TRACING: bool);
+
+/// The `macro_rules!` macro defined in the current crate which `span` was
+/// expanded from, if [`ENABLE_MACRO_TRACING`] is set.
+fn user_macro(span: Span) -> Option<Symbol> {
+  if !ENABLE_MACRO_TRACING.copied().unwrap_or(false) || !span.from_expansion() {
+    return None;
+  }
+
+  let expn_data = span.ctxt().outer_expn_data();
+  match expn_data.kind {
+    ExpnKind::Macro(MacroKind::Bang, name)
+      if expn_data
+        .macro_def_id
+        .is_some_and(|def_id| def_id.is_local()) =>
+    {
+      Some(name)
+    }
+    _ => None,
+  }
+}
+
+/// Is `span` hidden from the user by an expansion or desugaring? Spans
+/// expanded from user macros are not, see [`user_macro`].
+fn is_hidden(span: Span) -> bool {
+  span.from_expansion() && user_macro(span).is_none()
+}
+
+/// The user macro a path use at `hir_id` happens in, e.g. `push!` for `v`
+/// in `push!(v, 1)` expanding to `v.push(1)`.
+pub(super) fn macro_of_use(tcx: TyCtxt, hir_id: HirId) -> Option<Symbol> {
+  let hir = tcx.hir();
+  [hir_id, tcx.parent_hir_id(hir_id)]
+    .into_iter()
+    .find_map(|hir_id| user_macro(hir.span(hir_id)))
+}
+
+/// The span of the local a place expression starts from, e.g. `v` in
+/// `&v.0[i]`.
+fn place_base_span(expr: &Expr) -> Option<Span> {
+  match expr.kind {
+    ExprKind::Field(base, _)
+    | ExprKind::Index(base, _, _)
+    | ExprKind::Unary(UnOp::Deref, base)
+    | ExprKind::AddrOf(_, _, base)
+    | ExprKind::MethodCall(_, base, _, _) => place_base_span(base),
+    ExprKind::Path(QPath::Resolved(
+      _,
+      Path {
+        span,
+        res: Res::Local(_),
+        ..
+      },
+    )) => Some(*span),
+    _ => None,
+  }
+}
+
 struct HirExprScraper<'tcx> {
   tcx: TyCtxt<'tcx>,
   typeck_res: &'tcx TypeckResults<'tcx>,
@@ -59,6 +130,193 @@ impl HirExprScraper<'_> {
       ExpectedPermissions::from_move()
     }
   }
+
+  /// Can the method `call` write to its receiver `rcvr` through a shared
+  /// reference? That is if it takes `&self` of a type with interior
+  /// mutability, i.e., containing an `UnsafeCell`, e.g., `Cell::set`.
+  fn is_interior_write(&self, call: &Expr, rcvr: &Expr) -> bool {
+    if !ENABLE_INTERIOR_MUTABILITY
+      .copied()
+      .unwrap_or(ENABLE_INTERIOR_DEFAULT)
+    {
+      return false;
+    }
+
+    let Some(def_id) = self.typeck_res.type_dependent_def_id(call.hir_id)
+    else {
+      return false;
+    };
+    let sig = self.tcx.fn_sig(def_id).instantiate_identity().skip_binder();
+    let takes_shared_ref = sig
+      .inputs()
+      .first()
+      .is_some_and(|ty| ty.ref_mutability() == Some(Mutability::Not));
+    if !takes_shared_ref {
+      return false;
+    }
+
+    self
+      .typeck_res
+      .expr_ty_adjusted(rcvr)
+      .builtin_deref(true)
+      .is_some_and(|ty| !ty.is_freeze(self.tcx, self.typing_env))
+  }
+
+  /// The trait method `expr` resolves to if it is an overloaded operator,
+  /// with the mutability of the implicit borrow of its operands.
+  fn overloaded_operator(&self, expr: &Expr) -> Option<OverloadedOperator> {
+    if !self.typeck_res.is_method_call(expr) {
+      return None;
+    }
+    let def_id = self.typeck_res.type_dependent_def_id(expr.hir_id)?;
+    let trait_def_id = self.tcx.trait_of_item(def_id)?;
+
+    // Operators taking their operands by value, e.g. `Add`, are moves or
+    // copies which the path boundaries already account for.
+    let sig = self.tcx.fn_sig(def_id).instantiate_identity().skip_binder();
+    let mutability = sig.inputs().first()?.ref_mutability()?;
+
+    Some(OverloadedOperator {
+      trait_name: self.tcx.item_name(trait_def_id).to_string(),
+      mutable: mutability.is_mut(),
+    })
+  }
+
+  /// Add boundaries for the operands implicitly borrowed by `expr` if it
+  /// is an overloaded operator, e.g. `&mut v` in `v[i] = x`, and visit the
+  /// remaining subexpressions. Returns false if `expr` is not one.
+  fn add_operator_boundaries(
+    &mut self,
+    expr: &'tcx Expr<'tcx>,
+    flow_context: HirId,
+  ) -> bool {
+    let (borrowed, rest): (&[&Expr], &[&Expr]) = match expr.kind {
+      ExprKind::Index(base, idx, _) => (&[base], &[idx]),
+      ExprKind::AssignOp(_, lhs, rhs) => (&[lhs], &[rhs]),
+      ExprKind::Unary(UnOp::Deref, inner) => (&[inner], &[]),
+      ExprKind::Binary(_, lhs, rhs) => (&[lhs, rhs], &[]),
+      _ => return false,
+    };
+    let Some(operator) = self.overloaded_operator(expr) else {
+      return false;
+    };
+
+    for operand in borrowed {
+      if !operand.is_syntactic_place_expr() || is_hidden(operand.span) {
+        self.visit_expr(operand);
+        continue;
+      }
+
+      let expected = if operator.mutable {
+        ExpectedPermissions::from_borrow(Mutability::Mut)
+      } else {
+        ExpectedPermissions::from_borrow(Mutability::Not)
+      };
+      self.data.push(PathBoundary {
+        hir_id: operand.hir_id,
+        flow_context,
+        conflicting_node: rest.first().map(|e| e.hir_id),
+        location: operand.span.shrink_to_lo(),
+        expected,
+        operator: Some(operator.clone()),
+        desugaring: None,
+      });
+    }
+
+    for e in rest {
+      self.visit_expr(e);
+    }
+
+    true
+  }
+
+  /// Add a boundary for the operand of a desugared `for` loop, `?` or
+  /// `.await`, which is moved or borrowed by the call the desugaring wraps
+  /// it in, e.g. `IntoIterator::into_iter(v)`. The operand keeps the span
+  /// the user wrote, while the call is spanned to the desugaring.
+  fn add_desugared_operand_boundary(
+    &mut self,
+    operand: &'tcx Expr<'tcx>,
+    desugaring: Desugaring,
+    flow_context: HirId,
+  ) {
+    let (place, expected) = match operand.kind {
+      // e.g. `for x in &mut v`
+      ExprKind::AddrOf(_, mutability, inner) => {
+        (inner, ExpectedPermissions::from_borrow(mutability))
+      }
+      _ => (operand, self.get_adjusted_permissions(operand)),
+    };
+
+    if !place.is_syntactic_place_expr() || is_hidden(place.span) {
+      self.visit_expr(operand);
+      return;
+    }
+
+    self.data.push(PathBoundary {
+      hir_id: operand.hir_id,
+      flow_context,
+      conflicting_node: None,
+      location: place.span.shrink_to_lo(),
+      expected,
+      operator: None,
+      desugaring: Some(desugaring),
+    });
+  }
+
+  /// Add boundaries for the bindings within `pat`, which move, copy or
+  /// borrow parts of the matched place.
+  ///
+  /// The binding mode is the one resolved by type checking, such that
+  /// default binding modes, e.g. `Some(v)` matching on `&Option<T>`, are
+  /// seen as borrows.
+  fn add_binding_boundaries(&mut self, pat: &Pat) {
+    // Patterns of desugarings, e.g. the `Some(s)` of a `for s in v` loop,
+    // bind compiler temporaries rather than source-level places.
+    if pat.span.from_expansion() {
+      return;
+    }
+
+    let flow_context = FLOW_CONTEXT.copied().unwrap_or(pat.hir_id);
+    pat.walk_always(|p| {
+      let PatKind::Binding(_, _, ident, _) = p.kind else {
+        return;
+      };
+      if is_hidden(ident.span) {
+        return;
+      }
+      let Some(BindingMode(by_ref, _)) =
+        self.typeck_res.pat_binding_modes().get(p.hir_id).copied()
+      else {
+        return;
+      };
+
+      let expected = match by_ref {
+        ByRef::Yes(mutability) => ExpectedPermissions::from_borrow(mutability),
+        // A binding of the whole place, e.g. `let v = x`, is already
+        // covered by the boundary on the matched expression.
+        ByRef::No if p.hir_id == pat.hir_id => return,
+        ByRef::No => {
+          let ty = self.typeck_res.node_type(p.hir_id);
+          if ty.is_copyable(self.tcx, self.typing_env) {
+            ExpectedPermissions::from_copy()
+          } else {
+            ExpectedPermissions::from_move()
+          }
+        }
+      };
+
+      self.data.push(PathBoundary {
+        hir_id: p.hir_id,
+        flow_context,
+        conflicting_node: None,
+        location: ident.span.shrink_to_lo(),
+        expected,
+        operator: None,
+        desugaring: None,
+      });
+    });
+  }
 }
 
 impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
@@ -81,6 +339,21 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
     intravisit::walk_stmt(self, stmt);
   }
 
+  fn visit_local(&mut self, local: &'tcx LetStmt<'tcx>) {
+    self.add_binding_boundaries(local.pat);
+    intravisit::walk_local(self, local);
+  }
+
+  fn visit_let_expr(&mut self, lex: &'tcx LetExpr<'tcx>) {
+    self.add_binding_boundaries(lex.pat);
+    intravisit::walk_let_expr(self, lex);
+  }
+
+  fn visit_arm(&mut self, arm: &'tcx Arm<'tcx>) {
+    self.add_binding_boundaries(arm.pat);
+    intravisit::walk_arm(self, arm);
+  }
+
   fn visit_block(&mut self, block: &'tcx Block) {
     for stmt in block.stmts.iter() {
       self.visit_stmt(stmt);
@@ -101,7 +374,33 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
       self.nested_visit_map().node_to_string(hir_id)
     );
 
+    if self.add_operator_boundaries(expr, flow_context) {
+      return;
+    }
+
     match expr.kind {
+      ExprKind::Match(
+        Expr {
+          kind: ExprKind::Call(_, [operand]),
+          ..
+        },
+        arms,
+        source @ (MatchSource::ForLoopDesugar
+        | MatchSource::TryDesugar(_)
+        | MatchSource::AwaitDesugar),
+      ) => {
+        let desugaring = match source {
+          MatchSource::ForLoopDesugar => Desugaring::ForLoop,
+          MatchSource::TryDesugar(_) => Desugaring::Try,
+          _ => Desugaring::Await,
+        };
+        self.add_desugared_operand_boundary(operand, desugaring, flow_context);
+
+        for a in arms.iter() {
+          self.visit_arm(a);
+        }
+      }
+
       // Method calls are a form of type-deref coercion which can
       // rely on the adjusted permissions rather than needing to
       // inspect the function signature.
@@ -115,6 +414,8 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
           flow_context,
           conflicting_node: None,
           expected,
+          operator: None,
+          desugaring: None,
         };
 
         self.data.push(pb);
@@ -125,16 +426,21 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
       }
 
       ExprKind::MethodCall(_, rcvr, args, fn_span)
-        if !fn_span.from_expansion()
+        if !is_hidden(fn_span)
           && rcvr.is_place_expr(|e| !matches!(e.kind, ExprKind::Lit(_))) =>
       {
-        let expected = self.get_adjusted_permissions(rcvr);
+        let mut expected = self.get_adjusted_permissions(rcvr);
+        if !expected.0.write && self.is_interior_write(expr, rcvr) {
+          expected = expected.with_interior_write();
+        }
         let pb = PathBoundary {
           location: rcvr.span,
           hir_id: rcvr.hir_id,
           flow_context,
           conflicting_node: None,
           expected,
+          operator: None,
+          desugaring: None,
         };
 
         self.data.push(pb);
@@ -145,7 +451,7 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
       }
 
       ExprKind::AddrOf(_, mutability, inner)
-        if inner.is_syntactic_place_expr() && !inner.span.from_expansion() =>
+        if inner.is_syntactic_place_expr() && !is_hidden(inner.span) =>
       {
         // We don't have to account for adjusted types because
         // taking a borrow provides explicit types.
@@ -155,6 +461,8 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
           conflicting_node: None,
           location: inner.span.shrink_to_lo(),
           expected: ExpectedPermissions::from_borrow(mutability),
+          operator: None,
+          desugaring: None,
         };
 
         self.data.push(pb);
@@ -172,14 +480,19 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
         rhs,
         _,
       ) => {
-        let pb = PathBoundary {
-          location: lhs.span.shrink_to_lo(),
-          hir_id: lhs.hir_id,
-          flow_context,
-          conflicting_node: Some(rhs.hir_id),
-          expected: ExpectedPermissions::from_assignment(),
-        };
-        self.data.push(pb);
+        // An overloaded `DerefMut` borrows the operand instead.
+        if !self.add_operator_boundaries(lhs, flow_context) {
+          let pb = PathBoundary {
+            location: lhs.span.shrink_to_lo(),
+            hir_id: lhs.hir_id,
+            flow_context,
+            conflicting_node: Some(rhs.hir_id),
+            expected: ExpectedPermissions::from_assignment(),
+            operator: None,
+            desugaring: None,
+          };
+          self.data.push(pb);
+        }
         self.visit_expr(rhs);
       }
 
@@ -194,9 +507,13 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
       // ```
       //
       // `s` would not have write permissions because it is not yet initialized.
-      // For now, the LHS is simply ignored from the boundaries analysis.
+      // For now, the LHS is simply ignored from the boundaries analysis,
+      // unless it is an overloaded index, e.g. `v[i] = x` which borrows
+      // `v` mutably.
       ExprKind::Assign(lhs, rhs, _) => {
-        log::debug!("ASSIGN: ignoring LHS {lhs:#?}");
+        if !self.add_operator_boundaries(lhs, flow_context) {
+          log::debug!("ASSIGN: ignoring LHS {lhs:#?}");
+        }
         self.visit_expr(rhs);
       }
 
@@ -210,6 +527,8 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
           flow_context,
           conflicting_node: Some(rhs.hir_id),
           expected: ExpectedPermissions::from_assignment(),
+          operator: None,
+          desugaring: None,
         };
 
         self.data.push(pb);
@@ -217,7 +536,7 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
       }
 
       ExprKind::Unary(UnOp::Deref, inner)
-        if inner.is_syntactic_place_expr() && !inner.span.from_expansion() =>
+        if inner.is_syntactic_place_expr() && !is_hidden(inner.span) =>
       {
         let pb = PathBoundary {
           hir_id,
@@ -226,6 +545,8 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
           // We want the boundary to appear to the left of the deref.
           location: expr.span.shrink_to_lo(),
           expected: self.get_adjusted_permissions(expr),
+          operator: None,
+          desugaring: None,
         };
         self.data.push(pb);
       }
@@ -238,13 +559,15 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
           res: Res::Local(_),
           ..
         },
-      )) if !span.from_expansion() => {
+      )) if !is_hidden(span) => {
         let pb = PathBoundary {
           hir_id,
           flow_context,
           conflicting_node: None,
           location: span.shrink_to_lo(),
           expected: self.get_adjusted_permissions(expr),
+          operator: None,
+          desugaring: None,
         };
         self.data.push(pb);
       }
@@ -274,9 +597,27 @@ pub(super) fn get_path_boundaries(
 
   finder.visit_nested_body(body_id);
 
-  if let Some((_, msg)) = finder.unsupported_feature {
-    bail!(msg);
+  if let Some((span, msg)) = finder.unsupported_feature {
+    let range = CharRange::from_span(span, tcx.sess.source_map()).ok();
+    return Err(AquascopeError::UnsupportedFeature { msg, range }.into());
   }
 
-  Ok(finder.data)
+  // Boundaries traced into a user macro are placed on the tokens passed
+  // to the macro, if the path use is built from them.
+  let boundaries = finder
+    .data
+    .into_iter()
+    .filter_map(|mut pb| {
+      if user_macro(pb.location).is_some() {
+        let Node::Expr(expr) = tcx.hir_node(pb.hir_id) else {
+          return None;
+        };
+        let base = place_base_span(expr).filter(|s| !s.from_expansion())?;
+        pb.location = base.shrink_to_lo();
+      }
+      Some(pb)
+    })
+    .collect();
+
+  Ok(boundaries)
 }
@@ -0,0 +1,171 @@
+//! Permissions of accesses through raw pointers.
+//!
+//! The borrow checker does not check places reached through a raw pointer,
+//! e.g., `*p` for `p: *mut T` within an `unsafe` block. Boundaries on these
+//! places are marked as unchecked, while still expecting the permissions
+//! an equivalent safe reference would have required. The places a raw
+//! pointer was created from, i.e., the places it may alias, are then
+//! checked for those permissions instead.
+
+use rustc_data_structures::fx::FxHashSet as HashSet;
+use rustc_middle::{
+  mir::{
+    Body, Local, Location, Place, PlaceRef, ProjectionElem, Rvalue,
+    StatementKind,
+  },
+  ty::TyCtxt,
+};
+use rustc_utils::{source_map::range::CharRange, PlaceExt, SpanExt};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::analysis::{
+  permissions::{Permissions, Point},
+  AquascopeAnalysis,
+};
+
+/// An access through a raw pointer, which the borrow checker does not check.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct UncheckedAccess {
+  /// The places the raw pointer may alias.
+  pub aliases: Vec<AliasedPlace>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AliasedPlace {
+  pub path: String,
+  /// Where the raw pointer was created from the place.
+  pub created_at: CharRange,
+  /// The permissions of the place at the access.
+  pub permissions: Permissions,
+  /// Does the place lack permissions expected of the access?
+  pub is_violation: bool,
+}
+
+impl UncheckedAccess {
+  pub fn is_violation(&self) -> bool {
+    self.aliases.iter().any(|alias| alias.is_violation)
+  }
+}
+
+/// The last raw pointer dereferenced in `place`, if any.
+fn raw_pointer_deref<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  body: &Body<'tcx>,
+  place: Place<'tcx>,
+) -> Option<PlaceRef<'tcx>> {
+  place
+    .iter_projections()
+    .filter(|(base, elem)| {
+      matches!(elem, ProjectionElem::Deref)
+        && base.ty(body, tcx).ty.is_unsafe_ptr()
+    })
+    .map(|(base, _)| base)
+    .next_back()
+}
+
+/// Check the places aliased by an access to `place` at `point`, if `place`
+/// is reached through a raw pointer, against the `expected` permissions.
+pub(super) fn get_unchecked_access<'tcx>(
+  analysis: &AquascopeAnalysis<'tcx>,
+  place: Place<'tcx>,
+  point: Point,
+  expected: Permissions,
+) -> Option<UncheckedAccess> {
+  let ctxt = &analysis.permissions;
+  let tcx = ctxt.tcx;
+  let body = &ctxt.body_with_facts.body;
+
+  let pointer = raw_pointer_deref(tcx, body, place)?;
+
+  // Only pointers stored directly in locals are traced.
+  let targets = match pointer.as_local() {
+    Some(local) => pointer_targets(body, local),
+    None => Vec::new(),
+  };
+
+  let rest = &place.projection[pointer.projection.len() + 1 ..];
+  let aliases = targets
+    .into_iter()
+    .filter_map(|(location, target)| {
+      let target = target.project_deeper(rest, tcx);
+      let path = ctxt.try_place_to_path(&target)?;
+      let permissions = ctxt
+        .permissions_data_at_point(path, point)
+        .permissions_ignore_liveness();
+      let is_violation = (expected.read && !permissions.read)
+        || (expected.write && !permissions.write);
+
+      let span = ctxt.location_to_span(location);
+      let span = span.as_local(body.span).unwrap_or(span);
+      Some(AliasedPlace {
+        path: target
+          .to_string(tcx, body)
+          .unwrap_or_else(|| format!("{target:?}")),
+        created_at: analysis.span_to_range(span),
+        permissions,
+        is_violation,
+      })
+    })
+    .collect();
+
+  Some(UncheckedAccess { aliases })
+}
+
+/// The places a raw pointer stored in `local` may have been created from,
+/// with the location of their borrow.
+///
+/// Pointers are followed through copies and casts, and references
+/// coerced to raw pointers are followed to the place they borrow.
+fn pointer_targets<'tcx>(
+  body: &Body<'tcx>,
+  local: Local,
+) -> Vec<(Location, Place<'tcx>)> {
+  let mut targets = Vec::new();
+  let mut visited = HashSet::default();
+  let mut stack = vec![local];
+
+  while let Some(local) = stack.pop() {
+    if !visited.insert(local) {
+      continue;
+    }
+
+    for (block, data) in body.basic_blocks.iter_enumerated() {
+      for (statement_index, stmt) in data.statements.iter().enumerate() {
+        let StatementKind::Assign(box (lhs, rvalue)) = &stmt.kind else {
+          continue;
+        };
+        if lhs.as_local() != Some(local) {
+          continue;
+        }
+
+        match rvalue {
+          Rvalue::RawPtr(_, place) | Rvalue::Ref(_, _, place) => {
+            // A reborrow of another pointer, e.g., `&raw mut *r`.
+            if let [ProjectionElem::Deref] = place.projection[..] {
+              stack.push(place.local);
+            } else {
+              let location = Location {
+                block,
+                statement_index,
+              };
+              targets.push((location, *place));
+            }
+          }
+          Rvalue::Use(op) | Rvalue::Cast(_, op, _) => {
+            if let Some(place) = op.place()
+              && let Some(local) = place.as_local()
+            {
+              stack.push(local);
+            }
+          }
+          _ => {}
+        }
+      }
+    }
+  }
+
+  targets
+}
@@ -0,0 +1,289 @@
+//! Explaining why a loan is live at a point.
+//!
+//! A loan refines the permissions of a path for as long as it is live,
+//! i.e., while some origin containing it is live. Polonius knows which
+//! origins contain the loan (`origin_contains_loan_at`) and which are
+//! live (`origin_live_on_entry`), but not why. An explanation follows the
+//! loan from the origin it was issued into, along the `subset_base` edges,
+//! to a live origin. That origin is part of the type of a variable, whose
+//! later use keeps the origin, and therefore the loan, live.
+
+use std::collections::VecDeque;
+
+use rustc_data_structures::fx::{FxHashMap as HashMap, FxHashSet as HashSet};
+use rustc_middle::mir::{Local, Location, VarDebugInfoContents};
+use rustc_utils::{source_map::range::CharRange, SpanExt};
+use serde::Serialize;
+use ts_rs::TS;
+
+use super::{
+  permissions::{Loan, Origin, Point},
+  AquascopeAnalysis, LoanKey,
+};
+
+/// Why a loan is live at a point.
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct LoanExplanation {
+  pub loan: LoanKey,
+  /// The variable whose type contains a live origin holding the loan.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub holder: Option<ExplanationVar>,
+  /// The later use of the holder which keeps the loan live.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub later_use: Option<CharRange>,
+  /// The flows of the loan from where it was issued into the holder.
+  pub flows: Vec<LoanFlow>,
+}
+
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct ExplanationVar {
+  pub name: String,
+  /// Where the variable was declared.
+  pub range: CharRange,
+}
+
+/// A `subset_base` edge along which a loan flows.
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct LoanFlow {
+  /// The variable whose type contains the source origin, if any.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub from: Option<String>,
+  /// The variable whose type contains the target origin, if any.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub to: Option<String>,
+  /// Where the flow occurs.
+  pub range: CharRange,
+}
+
+impl AquascopeAnalysis<'_> {
+  /// Explain why `loan` is live at `location`, `None` if it isn't.
+  pub fn explain_loan(
+    &self,
+    loan: LoanKey,
+    location: Location,
+  ) -> Option<LoanExplanation> {
+    let ctxt = &self.permissions;
+    let facts = ctxt.polonius_input_facts;
+    let output = &ctxt.polonius_output;
+    let point = ctxt.location_to_point(location);
+    let loan_idx = Loan::from_u32(*loan);
+
+    let (issued_origin, _, _) = facts
+      .loan_issued_at
+      .iter()
+      .find(|(_, l, _)| *l == loan_idx)?;
+
+    let live_origins = output
+      .origin_live_on_entry
+      .get(&point)
+      .map(|origins| origins.iter().copied().collect::<HashSet<_>>())
+      .unwrap_or_default();
+    let holding = output
+      .origin_contains_loan_at
+      .get(&point)?
+      .iter()
+      .filter(|(origin, loans)| {
+        loans.contains(&loan_idx) && live_origins.contains(*origin)
+      })
+      .map(|(origin, _)| *origin)
+      .collect::<Vec<_>>();
+    if holding.is_empty() {
+      return None;
+    }
+
+    let vars = self.origin_variables();
+
+    // Prefer a live origin held by a named variable which is used later.
+    let holder = holding.iter().find_map(|origin| {
+      vars.get(origin)?.iter().find_map(|(local, name)| {
+        let used_at = self.next_use(*local, point)?;
+        Some((*origin, *local, name.clone(), used_at))
+      })
+    });
+    let target = holder.as_ref().map_or(holding[0], |(origin, ..)| *origin);
+
+    let flows = self
+      .subset_path(*issued_origin, target)
+      .into_iter()
+      .map(|(from, to, point)| {
+        let name_of = |origin: Origin| {
+          vars
+            .get(&origin)
+            .and_then(|holders| holders.first())
+            .map(|(_, name)| name.clone())
+        };
+        LoanFlow {
+          from: name_of(from),
+          to: name_of(to),
+          range: self.point_to_range(point),
+        }
+      })
+      .collect();
+
+    let body = &ctxt.body_with_facts.body;
+    let (holder, later_use) = match holder {
+      Some((_, local, name, used_at)) => {
+        let span = body.local_decls[local].source_info.span;
+        let var = ExplanationVar {
+          name,
+          range: self.span_to_range(span),
+        };
+        (Some(var), Some(self.point_to_range(used_at)))
+      }
+      None => (None, None),
+    };
+
+    Some(LoanExplanation {
+      loan,
+      holder,
+      later_use,
+      flows,
+    })
+  }
+
+  /// The named variables whose types contain each origin.
+  fn origin_variables(&self) -> HashMap<Origin, Vec<(Local, String)>> {
+    let ctxt = &self.permissions;
+    let body = &ctxt.body_with_facts.body;
+    let names = body
+      .var_debug_info
+      .iter()
+      .filter_map(|info| match info.value {
+        VarDebugInfoContents::Place(place) if place.projection.is_empty() => {
+          Some((place.local, info.name.to_string()))
+        }
+        _ => None,
+      })
+      .collect::<HashMap<_, _>>();
+
+    let mut vars = HashMap::<Origin, Vec<(Local, String)>>::default();
+    for &(local, origin) in &ctxt.polonius_input_facts.use_of_var_derefs_origin
+    {
+      if let Some(name) = names.get(&local) {
+        vars.entry(origin).or_default().push((local, name.clone()));
+      }
+    }
+    vars
+  }
+
+  /// The closest point at or after `point` where `local` is used.
+  fn next_use(&self, local: Local, point: Point) -> Option<Point> {
+    let facts = self.permissions.polonius_input_facts;
+    let uses = facts
+      .var_used_at
+      .iter()
+      .chain(&facts.var_dropped_at)
+      .filter(|(var, _)| *var == local)
+      .map(|(_, point)| *point)
+      .collect::<HashSet<_>>();
+
+    let mut successors = HashMap::<Point, Vec<Point>>::default();
+    for &(p1, p2) in &facts.cfg_edge {
+      successors.entry(p1).or_default().push(p2);
+    }
+
+    let mut visited = HashSet::default();
+    let mut queue = VecDeque::from([point]);
+    while let Some(p) = queue.pop_front() {
+      if uses.contains(&p) {
+        return Some(p);
+      }
+      if visited.insert(p) {
+        queue.extend(successors.get(&p).into_iter().flatten().copied());
+      }
+    }
+    None
+  }
+
+  /// The shortest chain of `subset_base` edges from `source` to `target`.
+  fn subset_path(
+    &self,
+    source: Origin,
+    target: Origin,
+  ) -> Vec<(Origin, Origin, Point)> {
+    let ctxt = &self.permissions;
+    let mut edges = HashMap::<Origin, Vec<(Origin, Point)>>::default();
+    for &(from, to, point) in &ctxt.polonius_input_facts.subset_base {
+      if !ctxt.is_universal_subset((from, to)) {
+        edges.entry(from).or_default().push((to, point));
+      }
+    }
+
+    let mut parent = HashMap::<Origin, (Origin, Point)>::default();
+    let mut queue = VecDeque::from([source]);
+    while let Some(origin) = queue.pop_front() {
+      if origin == target {
+        break;
+      }
+      for &(next, point) in edges.get(&origin).into_iter().flatten() {
+        if next != source && !parent.contains_key(&next) {
+          parent.insert(next, (origin, point));
+          queue.push_back(next);
+        }
+      }
+    }
+
+    let mut path = Vec::new();
+    let mut current = target;
+    while let Some(&(prev, point)) = parent.get(&current) {
+      path.push((prev, current, point));
+      current = prev;
+    }
+    path.reverse();
+    path
+  }
+
+  fn point_to_range(&self, point: Point) -> CharRange {
+    let ctxt = &self.permissions;
+    let span = ctxt.location_to_span(ctxt.point_to_location(point));
+    let span = span
+      .as_local(ctxt.body_with_facts.body.span)
+      .unwrap_or(span);
+    self.span_to_range(span)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use rustc_utils::source_map::range::CharPos;
+
+  use super::*;
+  use crate::{analysis::permissions::Refiner, test_utils as tu};
+
+  #[test]
+  fn test_explain_loan() {
+    let code = r#"
+fn main() {
+  let mut v = vec![1];
+  let r = &v;
+  let n = v.len();
+  println!("{r:?} {n}");
+}
+"#;
+    tu::compile_normal(code, |tcx| {
+      tu::for_each_body(tcx, |body_id, _| {
+        let analysis = AquascopeAnalysis::new(tcx, body_id);
+        let position = CharPos {
+          line: 4,
+          column: 10,
+        };
+        let query = analysis.query_permissions(position, "v").unwrap();
+        assert!(!query.permissions.write);
+
+        let explanation = query
+          .refiners
+          .iter()
+          .find(|r| matches!(r.refiner, Refiner::Loan(_)))
+          .and_then(|r| r.explanation.as_ref())
+          .expect("missing explanation");
+        let holder = explanation.holder.as_ref().unwrap();
+        assert_eq!(holder.name, "r");
+        let later_use = explanation.later_use.as_ref().unwrap();
+        assert_eq!(later_use.start.line, 5);
+      });
+    });
+  }
+}
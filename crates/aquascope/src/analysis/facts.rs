@@ -0,0 +1,426 @@
+//! Dumping the input facts of rustc and the derived Aquascope relations.
+//!
+//! Each relation is a table of readable atoms, matching the Datalog rules
+//! documented for [`Output`](super::permissions::Output):
+//! - points are MIR locations with their source position, e.g. `Mid(bb0[2])@3:11`,
+//! - paths are places, with their name if source-visible, e.g. `(*_1).0((*v).0)`,
+//!   the locals distinguish variables which shadow each other,
+//! - loans and moves are named by where they occur, e.g. `bw0@3:11`,
+//! - variables are locals, with their name if they have one, e.g. `_1(v)`.
+//!
+//! Relations can be written as [Soufflé](https://souffle-lang.github.io)
+//! input facts, see [`FactRelation::to_souffle`] and
+//! [`FactRelation::to_souffle_decl`].
+
+use rustc_data_structures::fx::FxHashMap as HashMap;
+use rustc_hir::BodyId;
+use rustc_middle::{
+  mir::{Local, Location, Place},
+  ty::TyCtxt,
+};
+use rustc_mir_dataflow::move_paths::MovePathIndex;
+use rustc_utils::{PlaceExt, SpanExt};
+use serde::Serialize;
+
+use super::{
+  catch_analysis_panic,
+  permissions::{Loan, Move, Origin, Path, Point},
+  AnalysisStage, AquascopeAnalysis, AquascopeResult,
+};
+
+/// All relations of a body.
+#[derive(Clone, Debug, Serialize)]
+pub struct FactsDump {
+  /// Facts provided by rustc to Polonius.
+  pub input: Vec<FactRelation>,
+  /// Relations derived by Aquascope, see [`super::permissions::Output`].
+  pub aquascope: Vec<FactRelation>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FactRelation {
+  pub name: String,
+  pub columns: Vec<String>,
+  pub rows: Vec<Vec<String>>,
+}
+
+impl FactRelation {
+  fn new<const N: usize>(
+    name: &str,
+    columns: [&str; N],
+    rows: impl IntoIterator<Item = [String; N]>,
+  ) -> Self {
+    let mut rows = rows.into_iter().map(Vec::from).collect::<Vec<_>>();
+    rows.sort();
+    FactRelation {
+      name: name.to_string(),
+      columns: columns.into_iter().map(String::from).collect(),
+      rows,
+    }
+  }
+
+  /// The Soufflé declaration of the relation, reading its rows from
+  /// `<name>.facts`. Every atom is a `symbol`.
+  pub fn to_souffle_decl(&self) -> String {
+    let columns = self
+      .columns
+      .iter()
+      .map(|column| format!("{column}: symbol"))
+      .collect::<Vec<_>>()
+      .join(", ");
+    format!(".decl {}({columns})\n.input {}\n", self.name, self.name)
+  }
+
+  /// The rows of the relation in the tab-separated format of Soufflé
+  /// `.facts` files.
+  pub fn to_souffle(&self) -> String {
+    self
+      .rows
+      .iter()
+      .map(|row| {
+        let mut line = row.join("\t");
+        line.push('\n');
+        line
+      })
+      .collect()
+  }
+}
+
+impl<'tcx> AquascopeAnalysis<'tcx> {
+  /// Analyze the body of `body_id` and dump its facts, see
+  /// [`AquascopeAnalysis::facts`].
+  pub fn dump_facts(
+    tcx: TyCtxt<'tcx>,
+    body_id: BodyId,
+  ) -> AquascopeResult<FactsDump> {
+    catch_analysis_panic(tcx, body_id, AnalysisStage::Permissions, || {
+      Self::new(tcx, body_id).facts()
+    })
+  }
+
+  /// Dump the input facts and derived relations of the body.
+  pub fn facts(&self) -> FactsDump {
+    FactsDump {
+      input: self.input_relations(),
+      aquascope: self.aquascope_relations(),
+    }
+  }
+
+  fn input_relations(&self) -> Vec<FactRelation> {
+    let ctxt = &self.permissions;
+    let facts = ctxt.polonius_input_facts;
+    let o = |origin: &Origin| format!("{origin:?}");
+    let p = |point: &Point| self.point_atom(*point);
+    let l = |loan: &Loan| self.loan_atom(*loan);
+    let v = |local: &Local| self.variable_atom(*local);
+    let mp = |path: &MovePathIndex| {
+      let place = ctxt.move_data.move_paths[*path].place;
+      self.place_atom(place)
+    };
+
+    vec![
+      FactRelation::new(
+        "loan_issued_at",
+        ["origin", "loan", "point"],
+        facts
+          .loan_issued_at
+          .iter()
+          .map(|(origin, loan, point)| [o(origin), l(loan), p(point)]),
+      ),
+      FactRelation::new(
+        "universal_region",
+        ["origin"],
+        facts.universal_region.iter().map(|origin| [o(origin)]),
+      ),
+      FactRelation::new(
+        "cfg_edge",
+        ["point1", "point2"],
+        facts.cfg_edge.iter().map(|(p1, p2)| [p(p1), p(p2)]),
+      ),
+      FactRelation::new(
+        "loan_killed_at",
+        ["loan", "point"],
+        facts
+          .loan_killed_at
+          .iter()
+          .map(|(loan, point)| [l(loan), p(point)]),
+      ),
+      FactRelation::new(
+        "subset_base",
+        ["origin1", "origin2", "point"],
+        facts
+          .subset_base
+          .iter()
+          .map(|(o1, o2, point)| [o(o1), o(o2), p(point)]),
+      ),
+      FactRelation::new(
+        "loan_invalidated_at",
+        ["point", "loan"],
+        facts
+          .loan_invalidated_at
+          .iter()
+          .map(|(point, loan)| [p(point), l(loan)]),
+      ),
+      FactRelation::new(
+        "var_used_at",
+        ["variable", "point"],
+        facts
+          .var_used_at
+          .iter()
+          .map(|(var, point)| [v(var), p(point)]),
+      ),
+      FactRelation::new(
+        "var_defined_at",
+        ["variable", "point"],
+        facts
+          .var_defined_at
+          .iter()
+          .map(|(var, point)| [v(var), p(point)]),
+      ),
+      FactRelation::new(
+        "var_dropped_at",
+        ["variable", "point"],
+        facts
+          .var_dropped_at
+          .iter()
+          .map(|(var, point)| [v(var), p(point)]),
+      ),
+      FactRelation::new(
+        "use_of_var_derefs_origin",
+        ["variable", "origin"],
+        facts
+          .use_of_var_derefs_origin
+          .iter()
+          .map(|(var, origin)| [v(var), o(origin)]),
+      ),
+      FactRelation::new(
+        "drop_of_var_derefs_origin",
+        ["variable", "origin"],
+        facts
+          .drop_of_var_derefs_origin
+          .iter()
+          .map(|(var, origin)| [v(var), o(origin)]),
+      ),
+      FactRelation::new(
+        "child_path",
+        ["child", "parent"],
+        facts
+          .child_path
+          .iter()
+          .map(|(child, parent)| [mp(child), mp(parent)]),
+      ),
+      FactRelation::new(
+        "path_is_var",
+        ["path", "variable"],
+        facts
+          .path_is_var
+          .iter()
+          .map(|(path, var)| [mp(path), v(var)]),
+      ),
+      FactRelation::new(
+        "path_assigned_at_base",
+        ["path", "point"],
+        facts
+          .path_assigned_at_base
+          .iter()
+          .map(|(path, point)| [mp(path), p(point)]),
+      ),
+      FactRelation::new(
+        "path_moved_at_base",
+        ["path", "point"],
+        facts
+          .path_moved_at_base
+          .iter()
+          .map(|(path, point)| [mp(path), p(point)]),
+      ),
+      FactRelation::new(
+        "path_accessed_at_base",
+        ["path", "point"],
+        facts
+          .path_accessed_at_base
+          .iter()
+          .map(|(path, point)| [mp(path), p(point)]),
+      ),
+      FactRelation::new(
+        "known_placeholder_subset",
+        ["origin1", "origin2"],
+        facts
+          .known_placeholder_subset
+          .iter()
+          .map(|(o1, o2)| [o(o1), o(o2)]),
+      ),
+      FactRelation::new(
+        "placeholder",
+        ["origin", "loan"],
+        facts
+          .placeholder
+          .iter()
+          .map(|(origin, loan)| [o(origin), l(loan)]),
+      ),
+    ]
+  }
+
+  fn aquascope_relations(&self) -> Vec<FactRelation> {
+    let ctxt = &self.permissions;
+    let output = &ctxt.permissions_output;
+    let p = |point: &Point| self.point_atom(*point);
+    let l = |loan: &Loan| self.loan_atom(*loan);
+    let m = |mv: &Move| self.move_atom(*mv);
+    let path = |path: &Path| self.place_atom(ctxt.path_to_place(*path));
+
+    let refined =
+      |name: &str, refined: &HashMap<Point, HashMap<Path, Loan>>| {
+        FactRelation::new(
+          name,
+          ["path", "loan", "point"],
+          refined.iter().flat_map(|(point, paths)| {
+            paths.iter().map(|(pa, loan)| [path(pa), l(loan), p(point)])
+          }),
+        )
+      };
+
+    vec![
+      FactRelation::new(
+        "never_write",
+        ["path"],
+        output.never_write.iter().map(|pa| [path(pa)]),
+      ),
+      refined("loan_read_refined", &output.loan_read_refined),
+      refined("loan_write_refined", &output.loan_write_refined),
+      refined("loan_drop_refined", &output.loan_drop_refined),
+      FactRelation::new(
+        "path_maybe_uninitialized_on_entry",
+        ["path", "point"],
+        output.path_maybe_uninitialized_on_entry.iter().flat_map(
+          |(point, paths)| paths.iter().map(|pa| [path(pa), p(point)]),
+        ),
+      ),
+      FactRelation::new(
+        "move_refined",
+        ["path", "move", "point"],
+        output.move_refined.iter().flat_map(|(point, paths)| {
+          paths.iter().map(|(pa, mv)| [path(pa), m(mv), p(point)])
+        }),
+      ),
+      FactRelation::new(
+        "move_live_at",
+        ["move", "point"],
+        output
+          .move_live_at
+          .iter()
+          .flat_map(|(point, moves)| moves.iter().map(|mv| [m(mv), p(point)])),
+      ),
+    ]
+  }
+
+  fn point_atom(&self, point: Point) -> String {
+    let ctxt = &self.permissions;
+    let location = ctxt.point_to_location(point);
+    let rich = if ctxt.location_to_point(location) == point {
+      "Start"
+    } else {
+      "Mid"
+    };
+    format!("{rich}({location:?})@{}", self.location_position(location))
+  }
+
+  /// Loans of placeholders aren't borrows, and have no location.
+  fn loan_atom(&self, loan: Loan) -> String {
+    let borrows = self.permissions.borrow_set.location_map();
+    match borrows.get_index(loan.as_usize()) {
+      Some((&location, _)) => {
+        format!("{loan:?}@{}", self.location_position(location))
+      }
+      None => format!("{loan:?}"),
+    }
+  }
+
+  fn move_atom(&self, mv: Move) -> String {
+    let location = self.permissions.move_data.moves[mv].source;
+    format!("{mv:?}@{}", self.location_position(location))
+  }
+
+  fn variable_atom(&self, local: Local) -> String {
+    let ctxt = &self.permissions;
+    let body = &ctxt.body_with_facts.body;
+    match Place::from(local).to_string(ctxt.tcx, body) {
+      Some(name) => format!("{local:?}({name})"),
+      None => format!("{local:?}"),
+    }
+  }
+
+  fn place_atom(&self, place: Place) -> String {
+    let ctxt = &self.permissions;
+    let body = &ctxt.body_with_facts.body;
+    if place.is_source_visible(ctxt.tcx, body)
+      && let Some(name) = place.to_string(ctxt.tcx, body)
+    {
+      format!("{place:?}({name})")
+    } else {
+      format!("{place:?}")
+    }
+  }
+
+  /// The 1-based `line:column` of `location` in the source.
+  fn location_position(&self, location: Location) -> String {
+    let ctxt = &self.permissions;
+    let span = ctxt.location_to_span(location);
+    let span = span
+      .as_local(ctxt.body_with_facts.body.span)
+      .unwrap_or(span);
+    let range = self.span_to_range(span);
+    format!("{}:{}", range.start.line + 1, range.start.column + 1)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::test_utils as tu;
+
+  #[test]
+  fn test_shadowed_paths() {
+    let code = "fn f() { let x = String::new(); let x = x; }";
+    tu::compile_normal(code, |tcx| {
+      tu::for_each_body(tcx, |body_id, _| {
+        let dump = AquascopeAnalysis::dump_facts(tcx, body_id).unwrap();
+        let path_is_var = dump
+          .input
+          .iter()
+          .find(|relation| relation.name == "path_is_var")
+          .unwrap();
+        let mut paths = path_is_var
+          .rows
+          .iter()
+          .map(|row| row[0].clone())
+          .filter(|path| path.ends_with("(x)"))
+          .collect::<Vec<_>>();
+        paths.sort();
+        paths.dedup();
+        assert_eq!(paths.len(), 2, "{paths:?}");
+      })
+    })
+  }
+
+  #[test]
+  fn test_souffle() {
+    let relation = FactRelation::new("cfg_edge", ["point1", "point2"], [
+      [
+        String::from("Start(bb0[1])@2:3"),
+        String::from("Mid(bb0[1])@2:3"),
+      ],
+      [
+        String::from("Start(bb0[0])@1:1"),
+        String::from("Mid(bb0[0])@1:1"),
+      ],
+    ]);
+    assert_eq!(
+      relation.to_souffle(),
+      "Start(bb0[0])@1:1\tMid(bb0[0])@1:1\nStart(bb0[1])@2:3\tMid(bb0[1])@2:3\n"
+    );
+    assert_eq!(
+      relation.to_souffle_decl(),
+      ".decl cfg_edge(point1: symbol, point2: symbol)\n.input cfg_edge\n"
+    );
+  }
+}
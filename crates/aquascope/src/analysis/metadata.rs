@@ -0,0 +1,90 @@
+//! Descriptive information about an analyzed body.
+//!
+//! An analysis is produced for every body in a crate, the metadata
+//! allows consumers to tell which item each result belongs to.
+
+use rustc_hir::{def::DefKind, BodyId};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::{def_id::LOCAL_CRATE, source_map::SourceMap, Span};
+use rustc_utils::source_map::range::CharRange;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::{AquascopeError, AquascopeResult};
+
+/// The kind of item which owns a body.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub enum BodyKind {
+  Fn,
+  /// A function within an inherent or trait `impl` block.
+  Method,
+  /// A default method body within a trait definition.
+  TraitMethod,
+  Closure,
+  /// Constants, including associated, anonymous and inline constants.
+  Const,
+  Static,
+  Other,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BodyMetadata {
+  /// The name of the crate being analyzed, useful to distinguish
+  /// results when analyzing every member of a workspace.
+  pub crate_name: String,
+  /// The path of the owning item, e.g. `my_mod::Foo::bar`.
+  pub def_path: String,
+  pub kind: BodyKind,
+  /// The file containing the body, as reported by rustc.
+  pub filename: String,
+  pub body_range: CharRange,
+}
+
+impl BodyMetadata {
+  /// Fails if the body's span can't be mapped back to its source, in which
+  /// case there is nothing to attach its analysis to.
+  pub fn new(tcx: TyCtxt, body_id: BodyId) -> AquascopeResult<Self> {
+    let hir = tcx.hir();
+    let def_id = hir.body_owner_def_id(body_id);
+    let def_path = tcx.def_path_str(def_id.to_def_id());
+
+    let kind = match tcx.def_kind(def_id) {
+      DefKind::Fn => BodyKind::Fn,
+      DefKind::AssocFn if tcx.trait_of_item(def_id.to_def_id()).is_some() => {
+        BodyKind::TraitMethod
+      }
+      DefKind::AssocFn => BodyKind::Method,
+      DefKind::Closure => BodyKind::Closure,
+      DefKind::Const
+      | DefKind::AssocConst
+      | DefKind::AnonConst
+      | DefKind::InlineConst => BodyKind::Const,
+      DefKind::Static { .. } => BodyKind::Static,
+      _ => BodyKind::Other,
+    };
+
+    let source_map = tcx.sess.source_map();
+    let span = hir.span_with_body(tcx.local_def_id_to_hir_id(def_id));
+    let filename = span_filename(source_map, span);
+    let body_range = CharRange::from_span(span, source_map).map_err(|e| {
+      AquascopeError::AnalysisError {
+        msg: format!("could not locate the body of {def_path}: {e}"),
+      }
+    })?;
+
+    Ok(BodyMetadata {
+      crate_name: tcx.crate_name(LOCAL_CRATE).to_string(),
+      def_path,
+      kind,
+      filename,
+      body_range,
+    })
+  }
+}
+
+/// The name of the file containing `span`, as rustc would report it.
+pub(crate) fn span_filename(source_map: &SourceMap, span: Span) -> String {
+  source_map.span_to_filename(span).prefer_local().to_string()
+}
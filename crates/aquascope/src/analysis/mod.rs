@@ -1,17 +1,24 @@
 //! Core contextual analysis for Aquascope.
 
 pub mod boundaries;
+pub mod explain;
+pub mod facts;
 pub mod find_bindings;
 pub mod ir_mapper;
+pub mod metadata;
 pub mod permissions;
+pub mod query;
+pub mod region_graph;
 mod scrape_hir;
 pub mod stepper;
 
 use std::{
+  any::Any,
   cell::RefCell,
   collections::HashMap,
   iter::IntoIterator,
   ops::{Add, Deref, DerefMut},
+  panic::{self, AssertUnwindSafe},
 };
 
 pub use boundaries::compute_permission_boundaries;
@@ -28,14 +35,22 @@ use rustc_middle::ty::TyCtxt;
 use rustc_span::{self, Span};
 use rustc_utils::{
   mir::borrowck_facts,
-  source_map::range::{CharPos, CharRange},
+  source_map::{
+    filename::FilenameIndex,
+    range::{CharPos, CharRange},
+  },
   BodyExt, SpanExt,
 };
-use serde::Serialize;
-pub use stepper::compute_permission_steps;
-use stepper::PermissionsLineDisplay;
+use serde::{Deserialize, Serialize};
+pub use stepper::{
+  compute_permission_steps, compute_permission_steps_and_timeline,
+  compute_permission_timeline,
+};
+use stepper::{PermissionsLineDisplay, PermissionsTimeline};
 use ts_rs::TS;
 
+use crate::errors;
+
 thread_local! {
   pub static BODY_ID_STACK: RefCell<Vec<BodyId>> =
     RefCell::new(Vec::default());
@@ -46,13 +61,33 @@ thread_local! {
 // conjunction with other data.
 
 #[derive(
-  Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, TS,
+  Clone,
+  Copy,
+  Debug,
+  Hash,
+  PartialEq,
+  Eq,
+  PartialOrd,
+  Ord,
+  Serialize,
+  Deserialize,
+  TS,
 )]
 #[ts(export)]
 pub struct LoanKey(pub u32);
 
 #[derive(
-  Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, TS,
+  Clone,
+  Copy,
+  Debug,
+  Hash,
+  PartialEq,
+  Eq,
+  PartialOrd,
+  Ord,
+  Serialize,
+  Deserialize,
+  TS,
 )]
 #[ts(export)]
 pub struct MoveKey(pub u32);
@@ -73,6 +108,11 @@ pub struct LoanRegions(pub HashMap<LoanKey, RefinementRegion>);
 #[ts(export)]
 pub struct MoveRegions(pub HashMap<MoveKey, RefinementRegion>);
 
+/// Maps the `filename` of each emitted [`CharRange`] to the path of its file.
+#[derive(Clone, Debug, Default, Serialize, TS)]
+#[ts(export)]
+pub struct FileTable(pub HashMap<FilenameIndex, String>);
+
 impl From<&Loan> for LoanKey {
   fn from(f: &Loan) -> LoanKey {
     LoanKey(f.as_u32())
@@ -238,9 +278,93 @@ pub fn compute_permissions<'tcx>(
 #[ts(export)]
 #[serde(tag = "type")]
 pub enum AquascopeError {
-  // An error occurred before the intended analysis could run.
-  BuildError { range: Option<CharRange> },
-  AnalysisError { msg: String },
+  // An error occurred before the intended analysis could run,
+  // e.g., the user's code does not type check.
+  BuildError {
+    range: Option<CharRange>,
+    // The message and code of the first error rustc reported.
+    msg: Option<String>,
+    code: Option<String>,
+  },
+  // The body uses a language feature that isn't supported by the analysis.
+  UnsupportedFeature {
+    msg: String,
+    range: Option<CharRange>,
+  },
+  // The stepper could not segment the body's MIR into steps.
+  StepperError {
+    msg: String,
+  },
+  // The analysis ran out of time before this body was analyzed.
+  Timeout {
+    limit_secs: u64,
+  },
+  // A permissions query named a position or path that doesn't
+  // resolve within the body.
+  InvalidQuery {
+    msg: String,
+  },
+  // An internal invariant was broken, i.e., a bug in Aquascope.
+  AnalysisError {
+    msg: String,
+  },
+  // The analysis panicked (or rustc ICE'd) while analyzing `body`.
+  AnalysisPanic {
+    stage: AnalysisStage,
+    msg: String,
+    body: String,
+  },
+}
+
+impl std::fmt::Display for AquascopeError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      AquascopeError::BuildError { msg, .. } => {
+        write!(f, "build error: {}", msg.as_deref().unwrap_or("unknown"))
+      }
+      AquascopeError::UnsupportedFeature { msg, .. } => {
+        write!(f, "unsupported feature: {msg}")
+      }
+      AquascopeError::StepperError { msg } => write!(f, "stepper error: {msg}"),
+      AquascopeError::Timeout { limit_secs } => {
+        write!(f, "analysis exceeded the time limit of {limit_secs}s")
+      }
+      AquascopeError::InvalidQuery { msg } => write!(f, "invalid query: {msg}"),
+      AquascopeError::AnalysisError { msg } => write!(f, "{msg}"),
+      AquascopeError::AnalysisPanic { stage, msg, body } => {
+        write!(f, "analysis of {body} panicked during {stage:?}: {msg}")
+      }
+    }
+  }
+}
+
+impl std::error::Error for AquascopeError {}
+
+/// A diagnostic reported by rustc for an analyzed body.
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct Diagnostic {
+  pub level: String,
+  pub code: Option<String>,
+  pub message: String,
+  pub labels: Vec<DiagnosticLabel>,
+}
+
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct DiagnosticLabel {
+  pub range: CharRange,
+  pub is_primary: bool,
+  pub label: Option<String>,
+}
+
+/// The stages of an [`AquascopeAnalysis`], used to report where it failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, TS)]
+#[ts(export)]
+pub enum AnalysisStage {
+  Permissions,
+  Boundaries,
+  Steps,
 }
 
 pub type AquascopeResult<T> = ::std::result::Result<T, AquascopeError>;
@@ -248,11 +372,15 @@ pub type AquascopeResult<T> = ::std::result::Result<T, AquascopeError>;
 pub struct AquascopeAnalysis<'tcx> {
   pub(crate) permissions: PermissionsCtxt<'tcx>,
   pub(crate) ir_mapper: IRMapper<'tcx>,
+  files: RefCell<FileTable>,
 }
 
 impl From<anyhow::Error> for AquascopeError {
   fn from(e: anyhow::Error) -> Self {
-    AquascopeError::AnalysisError { msg: e.to_string() }
+    // Analyses can return a more specific cause by bailing
+    // with an `AquascopeError`, anything else is a bug.
+    e.downcast::<AquascopeError>()
+      .unwrap_or_else(|e| AquascopeError::AnalysisError { msg: e.to_string() })
   }
 }
 
@@ -262,10 +390,41 @@ pub struct AnalysisOutput {
   pub body_range: CharRange,
   pub boundaries: Vec<PermissionsBoundary>,
   pub steps: Vec<PermissionsLineDisplay>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub timeline: Option<PermissionsTimeline>,
   pub loan_points: LoanPoints,
   pub loan_regions: LoanRegions,
   pub move_points: MovePoints,
   pub move_regions: MoveRegions,
+  pub diagnostics: Vec<Diagnostic>,
+  pub files: FileTable,
+}
+
+/// Run one stage of the analysis of `body_id`, turning a panic into an
+/// [`AquascopeError::AnalysisPanic`] so that other bodies can still be analyzed.
+fn catch_analysis_panic<T>(
+  tcx: TyCtxt,
+  body_id: BodyId,
+  stage: AnalysisStage,
+  f: impl FnOnce() -> T,
+) -> AquascopeResult<T> {
+  panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+    let def_id = tcx.hir().body_owner_def_id(body_id);
+    let body = tcx.def_path_str(def_id.to_def_id());
+    let msg = panic_message(payload.as_ref());
+    log::error!("Analysis of {body} panicked during {stage:?}: {msg}");
+    AquascopeError::AnalysisPanic { stage, msg, body }
+  })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+  if let Some(msg) = payload.downcast_ref::<&str>() {
+    (*msg).to_string()
+  } else if let Some(msg) = payload.downcast_ref::<String>() {
+    msg.clone()
+  } else {
+    String::from("unknown panic payload")
+  }
 }
 
 impl<'tcx> AquascopeAnalysis<'tcx> {
@@ -279,6 +438,7 @@ impl<'tcx> AquascopeAnalysis<'tcx> {
     AquascopeAnalysis {
       permissions,
       ir_mapper,
+      files: RefCell::default(),
     }
   }
 
@@ -286,36 +446,59 @@ impl<'tcx> AquascopeAnalysis<'tcx> {
     tcx: TyCtxt<'tcx>,
     body_id: BodyId,
   ) -> AquascopeResult<AnalysisOutput> {
-    let analysis_ctxt = Self::new(tcx, body_id);
+    use AnalysisStage as S;
+
+    let analysis_ctxt =
+      catch_analysis_panic(tcx, body_id, S::Permissions, || {
+        Self::new(tcx, body_id)
+      })?;
     let body = &analysis_ctxt.permissions.body_with_facts.body;
 
     if body.tainted_by_errors.is_some() {
       let span = body.span;
       let source_map = tcx.sess.source_map();
       let range = CharRange::from_span(span, source_map).unwrap().into();
-      return Err(AquascopeError::BuildError { range });
+      let def_id = tcx.hir().body_owner_def_id(body_id);
+      let (msg, code) = errors::get_first_error(tcx, def_id)
+        .map_or((None, None), |d| (Some(d.message), d.code));
+      return Err(AquascopeError::BuildError { range, msg, code });
     }
 
     crate::analysis::permissions::utils::dump_mir_debug(
       &analysis_ctxt.permissions,
     );
 
-    let boundaries = compute_permission_boundaries(&analysis_ctxt)?;
-    let steps = compute_permission_steps(&analysis_ctxt)?;
-
-    let (loan_points, loan_regions) = analysis_ctxt.construct_loan_info();
-    let (move_points, move_regions) = analysis_ctxt.construct_move_info();
+    let boundaries =
+      catch_analysis_panic(tcx, body_id, S::Boundaries, || {
+        compute_permission_boundaries(&analysis_ctxt)
+      })??;
+    let (steps, timeline) =
+      catch_analysis_panic(tcx, body_id, S::Steps, || {
+        compute_permission_steps_and_timeline(&analysis_ctxt)
+      })??;
+
+    let ((loan_points, loan_regions), (move_points, move_regions)) =
+      catch_analysis_panic(tcx, body_id, S::Permissions, || {
+        (
+          analysis_ctxt.construct_loan_info(),
+          analysis_ctxt.construct_move_info(),
+        )
+      })?;
 
     let body_range = analysis_ctxt.span_to_range(body.span);
+    let diagnostics = analysis_ctxt.construct_diagnostics();
 
     Ok(AnalysisOutput {
       body_range,
       boundaries,
       steps,
+      timeline,
       loan_points,
       loan_regions,
       move_points,
       move_regions,
+      diagnostics,
+      files: analysis_ctxt.files.take(),
     })
   }
 
@@ -329,7 +512,14 @@ impl<'tcx> AquascopeAnalysis<'tcx> {
     //   panic!("HERE YOU GO");
     // }
     let source_map = self.permissions.tcx.sess.source_map();
-    CharRange::from_span(span, source_map).unwrap()
+    let range = CharRange::from_span(span, source_map).unwrap();
+    self
+      .files
+      .borrow_mut()
+      .0
+      .entry(range.filename)
+      .or_insert_with(|| metadata::span_filename(source_map, span));
+    range
   }
 
   fn construct_loan_info(&self) -> (LoanPoints, LoanRegions) {
@@ -420,6 +610,31 @@ impl<'tcx> AquascopeAnalysis<'tcx> {
     (LoanPoints(loan_to_ranges), LoanRegions(loan_to_regions))
   }
 
+  fn construct_diagnostics(&self) -> Vec<Diagnostic> {
+    let def_id = self.permissions.def_id.expect_local();
+    errors::get_body_diagnostics(self.permissions.tcx, def_id)
+      .into_iter()
+      .map(|d| {
+        let labels = d
+          .labels
+          .into_iter()
+          .filter(|label| !label.span.is_dummy())
+          .map(|label| DiagnosticLabel {
+            range: self.span_to_range(label.span),
+            is_primary: label.is_primary,
+            label: label.label,
+          })
+          .collect::<Vec<_>>();
+        Diagnostic {
+          level: d.level.to_string(),
+          code: d.code,
+          message: d.message,
+          labels,
+        }
+      })
+      .collect()
+  }
+
   // FIXME(gavinleroy): the two `construct_XXX` methods could
   // be abstracted away better into one generic algorithm.
   fn construct_move_info(&self) -> (MovePoints, MoveRegions) {
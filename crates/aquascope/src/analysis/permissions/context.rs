@@ -30,7 +30,7 @@ use smallvec::{smallvec, SmallVec};
 use crate::analysis::permissions::{
   flow::RegionFlows, AquascopeFacts, Loan, LoanKey, Move, MoveKey, Origin,
   Output, Path, Permissions, PermissionsData, PermissionsDomain, Point,
-  Variable,
+  Variable, ENABLE_INTERIOR_DEFAULT, ENABLE_INTERIOR_MUTABILITY,
 };
 
 /// A path as defined in rustc.
@@ -83,19 +83,24 @@ impl<'tcx> PermissionsCtxt<'tcx> {
 
   /// Convert and normalize a rustc [`Place`] into a [`Path`] understood by Aquascope.
   pub fn place_to_path(&self, p: &Place<'tcx>) -> Path {
+    self.try_place_to_path(p).unwrap_or_else(|| {
+      panic!(
+        "Could not find path for place {p:?}\n Acceptable places are: {:#?}",
+        self.place_data.iter().collect::<Vec<_>>()
+      )
+    })
+  }
+
+  /// Like [`PermissionsCtxt::place_to_path`], but returns `None` for
+  /// places outside of the analyzed domain.
+  pub fn try_place_to_path(&self, p: &Place<'tcx>) -> Option<Path> {
     let p = p.normalize(self.tcx, self.def_id);
-    *self
+    self
       .rev_lookup
-      .get(&p.local)
-      .unwrap()
+      .get(&p.local)?
       .iter()
       .find(|path| self.path_to_place(**path) == p)
-      .unwrap_or_else(|| {
-        panic!(
-          "Could not find path for place {p:?}\n Acceptable places are: {:#?}",
-          self.place_data.iter().collect::<Vec<_>>()
-        )
-      })
+      .copied()
   }
 
   pub fn path_to_place(&self, p: Path) -> Place<'tcx> {
@@ -230,6 +235,23 @@ impl<'tcx> PermissionsCtxt<'tcx> {
     !self.permissions_output.never_write.contains(&path)
   }
 
+  /// Can this path be written to through a shared reference?
+  ///
+  /// This is the case for types containing an `UnsafeCell`, e.g., `Cell`,
+  /// `RefCell`, `Mutex` and atomics, if [`ENABLE_INTERIOR_MUTABILITY`] is set.
+  pub fn is_path_interior_write_enabled(&self, path: Path) -> bool {
+    if !ENABLE_INTERIOR_MUTABILITY
+      .copied()
+      .unwrap_or(ENABLE_INTERIOR_DEFAULT)
+    {
+      return false;
+    }
+    let body = &self.body_with_facts.body;
+    let place = self.path_to_place(path);
+    let ty = place.ty(&body.local_decls, self.tcx).ty;
+    !ty.is_freeze(self.tcx, self.typing_env)
+  }
+
   /// Does a Path's type allow it to be dropped?
   /// NOTE: the utility is the negation of the datalog rule.
   ///
@@ -281,11 +303,13 @@ impl<'tcx> PermissionsCtxt<'tcx> {
   pub fn max_permissions(&self, path: Path) -> Permissions {
     let write = self.is_path_write_enabled(path);
     let drop = self.is_path_drop_enabled(path);
+    let interior_write = self.is_path_interior_write_enabled(path);
     Permissions {
       write,
       // There is no way in the type system to have a non-readable type.
       read: true,
       drop,
+      interior_write,
     }
   }
 
@@ -294,6 +318,7 @@ impl<'tcx> PermissionsCtxt<'tcx> {
       is_live: true,
       type_droppable: true,
       type_writeable: true,
+      type_interior_writeable: false,
       type_copyable: ty.is_copyable(self.tcx, self.typing_env),
       path_moved: None,
       path_uninitialized: false,
@@ -369,6 +394,7 @@ impl<'tcx> PermissionsCtxt<'tcx> {
 
     let type_writeable = self.is_path_write_enabled(*path);
     let type_droppable = self.is_path_drop_enabled(*path);
+    let type_interior_writeable = self.is_path_interior_write_enabled(*path);
     let type_copyable = self.is_path_copyable(*path);
     let path_uninitialized = path_uninitialized.contains(path);
 
@@ -384,6 +410,7 @@ impl<'tcx> PermissionsCtxt<'tcx> {
     PermissionsData {
       type_droppable,
       type_writeable,
+      type_interior_writeable,
       type_copyable,
       is_live,
       path_uninitialized,
@@ -410,6 +437,7 @@ impl<'tcx> PermissionsCtxt<'tcx> {
           is_live: false,
           type_droppable: false,
           type_writeable: false,
+          type_interior_writeable: false,
           type_copyable: false,
           path_moved: None,
           path_uninitialized: false,
@@ -53,17 +53,56 @@
 //!  borrowfail G
 //! ```
 //!
-//! ### Other region related errors
+//! ### Hidden type capture
 //!
-//! Notably, there are other ways in which a region error could occur:
+//! An opaque type `impl Trait` can only capture the abstract origins ϱ_c
+//! among its arguments. A "hidden type" error occurs IFF the hidden type
+//! returned for it contains some other abstract origin:
 //!
-//! - A "hidden type" error occurs if a struct captures a lifetime that does not
-//!   appear in the resulting types member constraints (arising from `impl Trait`).
+//! ```text
+//! Θ ⊢ r_h : { ϱ }
+//! r_h -> impl Trait     ϱ ≠ 'static     ∀ ϱ_c. ϱ ⊈ ϱ_c
+//! -----------------------------------------------------
+//!                    borrowfail G
+//! ```
+//!
+//! An example of this (in the 2021 edition):
+//!
+//! ```rust,ignore
+//! fn iter<'a>(v: &'a Vec<i32>) -> impl Iterator<Item = i32> {
+//!   v.iter().copied()
+//! }
+//! ```
+//!
+//! These constraints are member constraints, which are not part of the
+//! `subset_base` facts, and are checked at the return sites of the body.
+//!
+//! ### Local outlives local
 //!
-//! - The simplest case, two concrete values involved in a region error.
-//!   See examples at: <https://doc.rust-lang.org/book/ch10-03-lifetime-syntax.html>.
+//! The simplest case, a borrow of a local source S flows into a region
+//! `r` which is used after S goes out of scope. A "local outlives" error
+//! within the body occurs IFF:
+//!
+//! ```text
+//! Θ ⊢ r_1 : { S }     r_1 -> r_2
+//! S dead at P     r_2 live at P
+//! -----------------------------
+//!         borrowfail G
+//! ```
 //!
-//! These types of region errors are not covered by this analysis and may be coming in the future.
+//! An example of this, see more at <https://doc.rust-lang.org/book/ch10-03-lifetime-syntax.html>:
+//!
+//! ```rust,ignore
+//! let r;
+//! {
+//!   let x = 5;
+//!   r = &x;
+//! }
+//! println!("r: {r}");
+//! ```
+//!
+//! Unlike the other rules, these errors are found by Polonius, whenever
+//! the `StorageDead` of S invalidates a live loan.
 //!
 //! ## Implementation details
 //!
@@ -75,21 +114,23 @@
 //! edge `'a -> 'b`. This graph forms the basis of flow analysis as outlined previously.
 use std::time::Instant;
 
+use either::Either;
 use itertools::Itertools;
 use rustc_borrowck::consumers::{
   places_conflict, BorrowData, PlaceConflictBias, PoloniusRegionVid,
 };
 use rustc_data_structures::{
-  fx::FxHashSet as HashSet,
+  fx::{FxHashMap as HashMap, FxHashSet as HashSet},
   graph::{depth_first_search, scc::Sccs, vec_graph::VecGraph, Successors},
   transitive_relation::{TransitiveRelation, TransitiveRelationBuilder},
 };
 use rustc_index::{bit_set::ChunkedBitSet, Idx};
+use rustc_middle::mir::{Statement, StatementKind};
 use rustc_utils::BodyExt;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
-use super::{Origin, PermissionsCtxt};
+use super::{Loan, Origin, PermissionsCtxt, Point};
 
 rustc_index::newtype_index! {
   #[derive(Ord, PartialOrd)]
@@ -103,7 +144,7 @@ impl polonius_engine::Atom for SccIdx {
   }
 }
 
-#[derive(Debug, Clone, Serialize, TS)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
 pub enum FlowEdgeKind {
   /// A local value is flowing into an abstract region.
@@ -121,6 +162,14 @@ pub enum FlowEdgeKind {
   /// for `LocalOutlivesUniversal`. Because it's more generic, this type of
   /// edge kind would be reported with lower priority.
   LocalInvalidatedAtExit,
+
+  /// The hidden type of an `impl Trait` contains an abstract region
+  /// which the opaque type does not capture.
+  HiddenTypeCapture,
+
+  /// A local value flows into a region which is used after
+  /// the value goes out of scope.
+  LocalOutlivesLocal,
   Ok,
 }
 
@@ -141,6 +190,12 @@ pub struct RegionFlows {
   /// The flow constraint graph over the `subset_base` relation.
   constraint_graph: Sccs<Origin, SccIdx>,
 
+  /// The number of origins in the constraint graph.
+  num_origins: usize,
+
+  /// The component of `'static`, if it appears in the body.
+  static_scc: Option<SccIdx>,
+
   /// Full set of known flows per the `known_placeholder_subset` relation.
   specified_flows: TransitiveRelation<SccIdx>,
 
@@ -150,6 +205,13 @@ pub struct RegionFlows {
   /// Regions that are equivalent to placeholders.
   abstract_sources: ChunkedBitSet<SccIdx>,
 
+  /// Regions of borrows of places owned by the body.
+  local_sources: ChunkedBitSet<SccIdx>,
+
+  /// Local regions whose place goes out of scope while borrowed, with the
+  /// loan of the borrow and the point of its `StorageDead`.
+  scope_ends: HashMap<SccIdx, (Loan, Point)>,
+
   /// The set of abstract components that a given component could contain.
   contains_abstract: TransitiveRelation<SccIdx>,
 
@@ -167,6 +229,25 @@ impl RegionFlows {
     self.abstract_sources.contains(self.scc(origin))
   }
 
+  /// Returns whether `origin` belongs to the SCC of a local borrow.
+  pub fn is_local_member(&self, origin: Origin) -> bool {
+    self.local_sources.contains(self.scc(origin))
+  }
+
+  /// Get the loan and end of scope of the first local in `origin` which
+  /// goes out of scope while borrowed, if any.
+  pub(crate) fn local_scope_end(
+    &self,
+    origin: Origin,
+  ) -> Option<(Loan, Point)> {
+    self
+      .contains_local
+      .reachable_from(self.scc(origin))
+      .into_iter()
+      .filter_map(|local| self.scope_ends.get(&local).copied())
+      .min_by_key(|(_, point)| *point)
+  }
+
   /// Returns whether `origin` is abstract-tainted.
   pub fn has_abstract_member(&self, origin: Origin) -> bool {
     !self
@@ -241,6 +322,12 @@ impl RegionFlows {
       return FlowEdgeKind::MissingUniversalConstraint;
     }
 
+    // Local values which go out of scope while flowing into another local
+    // region are reported before the more generic exit invalidation.
+    if !to_contains_abstract && self.local_scope_end(from).is_some() {
+      return FlowEdgeKind::LocalOutlivesLocal;
+    }
+
     // If `from` is flowing a dangling pointer we would always consider this an error.
     if self
       .contains_local
@@ -253,6 +340,42 @@ impl RegionFlows {
 
     FlowEdgeKind::Ok
   }
+
+  /// Get the kind of flow from the `hidden` regions of a hidden type into
+  /// an opaque type capturing the `captured` regions.
+  ///
+  /// Every abstract region of the hidden type must be `'static`, captured,
+  /// or specified to outlive a captured abstract region.
+  pub(crate) fn hidden_type_flow_kind(
+    &self,
+    hidden: &[Origin],
+    captured: &[Origin],
+  ) -> FlowEdgeKind {
+    let abstract_sources = |origins: &[Origin]| {
+      origins
+        .iter()
+        .filter(|origin| origin.index() < self.num_origins)
+        .flat_map(|&origin| {
+          self.contains_abstract.reachable_from(self.scc(origin))
+        })
+        .collect::<HashSet<_>>()
+    };
+    let captured = abstract_sources(captured);
+
+    let escapes = abstract_sources(hidden).into_iter().any(|from| {
+      Some(from) != self.static_scc
+        && !captured.contains(&from)
+        && !captured
+          .iter()
+          .any(|&to| self.specified_flows.contains(to, from))
+    });
+
+    if escapes {
+      FlowEdgeKind::HiddenTypeCapture
+    } else {
+      FlowEdgeKind::Ok
+    }
+  }
 }
 
 // ------------------
@@ -364,8 +487,8 @@ pub fn compute_flows(ctxt: &mut PermissionsCtxt) {
 
   // Graph of constraints that need to be satisfied. This shows
   // us how data flows from one region into another.
-  let constraint_graph =
-    VecGraph::<_, false>::new(count_nodes(&constraints), constraints);
+  let num_origins = count_nodes(&constraints);
+  let constraint_graph = VecGraph::<_, false>::new(num_origins, constraints);
 
   let scc_constraints = Sccs::<Origin, SccIdx>::new(&constraint_graph);
   let num_sccs = scc_constraints.num_sccs();
@@ -457,20 +580,54 @@ pub fn compute_flows(ctxt: &mut PermissionsCtxt) {
     }
   }
 
+  let mut errors = ctxt.polonius_output.errors.iter().collect::<Vec<_>>();
+  errors.sort_by_key(|(point, _)| **point);
+  let mut scope_ends = HashMap::default();
+  for (&point, loans) in errors {
+    let location = ctxt.point_to_location(point);
+    let Either::Left(Statement {
+      kind: StatementKind::StorageDead(local),
+      ..
+    }) = body.stmt_at(location)
+    else {
+      continue;
+    };
+    for &loan in loans.iter() {
+      let place = ctxt.loan_to_borrow(loan).borrowed_place();
+      if place.local == *local && !place.is_indirect() {
+        let scc = scc_constraints
+          .scc(PoloniusRegionVid::from(ctxt.loan_to_borrow(loan).region()));
+        scope_ends.entry(scc).or_insert((loan, point));
+      }
+    }
+  }
+
   let mut abstract_sources = ChunkedBitSet::new_empty(num_sccs);
   for scc in placeholders.iter() {
     abstract_sources.insert(*scc);
   }
 
+  // Rustc creates `'static` as the first universal region.
+  let static_scc = ctxt
+    .polonius_input_facts
+    .universal_region
+    .first()
+    .filter(|origin| vertices.contains(*origin))
+    .map(|&origin| scc_constraints.scc(origin));
+
   log::debug!("Contains abstract:\n{contains_abstract:#?}");
 
   log::debug!("Contains local:\n{contains_local:#?}");
 
   let region_flows = RegionFlows {
     constraint_graph: scc_constraints,
+    num_origins,
+    static_scc,
     specified_flows,
     dangling_local_sources,
     abstract_sources,
+    local_sources,
+    scope_ends,
     contains_abstract,
     contains_local,
   };
@@ -17,7 +17,7 @@ use rustc_borrowck::consumers::RustcFacts;
 use rustc_data_structures::fx::FxHashMap;
 use rustc_middle::mir::Place;
 use rustc_utils::source_map::range::CharRange;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
 use crate::analysis::{LoanKey, MoveKey};
@@ -25,6 +25,9 @@ use crate::analysis::{LoanKey, MoveKey};
 fluid_let!(pub static ENABLE_FLOW_PERMISSIONS: bool);
 pub const ENABLE_FLOW_DEFAULT: bool = false;
 
+fluid_let!(pub static ENABLE_INTERIOR_MUTABILITY: bool);
+pub const ENABLE_INTERIOR_DEFAULT: bool = false;
+
 /// Permission facts in Aquascope, similar to [`RustcFacts`].
 #[derive(Copy, Clone, Debug)]
 pub struct AquascopeFacts;
@@ -66,12 +69,17 @@ pub type Move = rustc_mir_dataflow::move_paths::MoveOutIndex;
 ///
 /// NOTE: previously, the term *drop* was used instead of *own*
 /// and this terminology remains within the source and internal documentation.
-#[derive(Clone, Copy, Hash, PartialEq, Eq, Serialize, TS)]
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize, TS)]
 #[ts(export)]
 pub struct Permissions {
   pub read: bool,
   pub write: bool,
   pub drop: bool,
+
+  /// Can the place be mutated through a shared reference, e.g., a `Cell`?
+  /// Only computed if [`ENABLE_INTERIOR_MUTABILITY`] is set.
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub interior_write: bool,
 }
 
 /// Permissions and first-order provenance for permission refinement.
@@ -80,7 +88,9 @@ pub struct Permissions {
 /// information about what factors into the permissions. Things like
 /// declared type information, loan refinements, move refinements, etc.
 /// `PermissionsData` corresponds to a single [`Place`].
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, TS)]
+#[derive(
+  Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize, TS,
+)]
 #[ts(export)]
 pub struct PermissionsData {
   /// Was the type declared as droppable (i.e. an owned value)?
@@ -89,6 +99,11 @@ pub struct PermissionsData {
   /// Was the type declared as writeable (i.e. is it `mut`)?
   pub type_writeable: bool,
 
+  /// Does the type contain an `UnsafeCell`, allowing writes through a
+  /// shared reference?
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub type_interior_writeable: bool,
+
   /// Is the type copyable (i.e. does it implement the `Copy` trait)?
   pub type_copyable: bool,
 
@@ -141,13 +156,33 @@ impl PermissionsData {
   /// - the path's declared type is droppable.
   /// - it isn't moved.
   /// - no drop-refining loan exists at this point.
+  ///
+  /// A path is interior-writeable IFF:
+  /// - the path's type contains an `UnsafeCell`.
+  /// - the path is readable, shared loans don't prevent interior writes.
   pub fn permissions_ignore_liveness(&self) -> Permissions {
     let mem_uninit = self.path_moved.is_some() || self.path_uninitialized;
     let read = !mem_uninit && self.loan_read_refined.is_none();
     let write =
       self.type_writeable && read && self.loan_write_refined.is_none();
     let drop = self.type_droppable && read && self.loan_drop_refined.is_none();
-    Permissions { read, write, drop }
+    let interior_write = self.type_interior_writeable && read;
+    Permissions {
+      read,
+      write,
+      drop,
+      interior_write,
+    }
+  }
+
+  /// The loan which removes interior-write permission, if any.
+  ///
+  /// Shared loans never refine interior writes, so this is the read-refining
+  /// (i.e., mutable) loan, and only for paths whose type permits them at all.
+  pub fn loan_interior_write_refined(&self) -> Option<LoanKey> {
+    self
+      .loan_read_refined
+      .filter(|_| self.type_interior_writeable)
   }
 }
 
@@ -181,13 +216,14 @@ impl Permissions {
       read: false,
       write: false,
       drop: false,
+      interior_write: false,
     }
   }
 }
 
 impl std::fmt::Debug for Permissions {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    if !self.read && !self.write && !self.drop {
+    if !self.read && !self.write && !self.drop && !self.interior_write {
       write!(f, "∅")
     } else {
       if self.read {
@@ -199,6 +235,9 @@ impl std::fmt::Debug for Permissions {
       if self.drop {
         write!(f, "D")?;
       }
+      if self.interior_write {
+        write!(f, "I")?;
+      }
       Ok(())
     }
   }
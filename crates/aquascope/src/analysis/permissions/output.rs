@@ -18,7 +18,7 @@ use rustc_hir::{BodyId, Mutability};
 use rustc_index::IndexVec;
 use rustc_middle::{
   mir::{Place, ProjectionElem},
-  ty::{TyCtxt, TypingEnv},
+  ty::{self, TyCtxt, TypingEnv},
 };
 use rustc_mir_dataflow::move_paths::MoveData;
 use rustc_utils::{BodyExt, PlaceExt};
@@ -369,11 +369,13 @@ pub fn derive_permission_facts(ctxt: &mut PermissionsCtxt) {
         })
         .any(|prefix| {
           // For a given path `*x` we could be looking at the prefix of
-          // `x`. This could be a reference, in which case we simply check
-          // the mutability of the type.
+          // `x`. This could be a reference or a raw pointer, in which case
+          // we simply check the mutability of the type.
           let ty = prefix.ty(&body.local_decls, tcx).ty;
-          if let Some(mutability) = ty.ref_mutability() {
-            return mutability == Mutability::Not;
+          if let ty::Ref(_, _, mutability) | ty::RawPtr(_, mutability) =
+            ty.kind()
+          {
+            return *mutability == Mutability::Not;
           }
 
           // In the above example of `*x`, example `x` could also be a
@@ -36,6 +36,7 @@ impl Permissions {
       read: false,
       write: false,
       drop: false,
+      interior_write: false,
     }
   }
 
@@ -44,6 +45,7 @@ impl Permissions {
       read: true,
       write: true,
       drop: true,
+      interior_write: true,
     }
   }
 }
@@ -54,6 +56,7 @@ impl JoinSemiLattice for Permissions {
     self.read &= other.read;
     self.write &= other.write;
     self.drop &= other.drop;
+    self.interior_write &= other.interior_write;
     orig != *self
   }
 }
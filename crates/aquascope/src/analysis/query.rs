@@ -0,0 +1,296 @@
+//! Querying the permissions of a path at a source position.
+//!
+//! Permissions are computed for MIR [`Place`]s at MIR points, which
+//! external callers (e.g., editors or scripts) have no access to. A query
+//! instead names a source position and a path expression like `v.0` or
+//! `*x`. The position resolves to the innermost expression containing it,
+//! whose permissions are those right before the expression is evaluated.
+
+use rustc_abi::FieldIdx;
+use rustc_hir::{
+  intravisit::{self, Visitor},
+  BodyId, Expr, HirId,
+};
+use rustc_middle::{
+  mir::{Location, Place, ProjectionElem, VarDebugInfoContents},
+  ty::{self, Ty, TyCtxt},
+};
+use rustc_span::Span;
+use rustc_utils::{
+  source_map::range::{CharPos, CharRange},
+  SpanExt,
+};
+use serde::Serialize;
+use ts_rs::TS;
+
+use super::{
+  catch_analysis_panic,
+  explain::LoanExplanation,
+  ir_mapper::GatherDepth,
+  permissions::{Loan, Move, Permissions, PermissionsData, Refiner},
+  AnalysisStage, AquascopeAnalysis, AquascopeError, AquascopeResult,
+};
+
+/// The permissions of a path at a source position.
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct PermissionsQuery {
+  /// The queried path, as provided.
+  pub path: String,
+  /// The expression the queried position resolved to.
+  pub range: CharRange,
+  pub permissions: Permissions,
+  pub data: PermissionsData,
+  /// The loans and moves responsible for refining the permissions.
+  pub refiners: Vec<RefinerPoint>,
+}
+
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct RefinerPoint {
+  pub refiner: Refiner,
+  /// Where the loan was created, or the path moved.
+  pub range: CharRange,
+  /// Why the loan is still live, for loans.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub explanation: Option<LoanExplanation>,
+}
+
+fn invalid_query(msg: String) -> AquascopeError {
+  AquascopeError::InvalidQuery { msg }
+}
+
+impl<'tcx> AquascopeAnalysis<'tcx> {
+  /// Analyze the body of `body_id` and query the permissions of
+  /// `path` at `position`, see [`AquascopeAnalysis::query_permissions`].
+  pub fn query(
+    tcx: TyCtxt<'tcx>,
+    body_id: BodyId,
+    position: CharPos,
+    path: &str,
+  ) -> AquascopeResult<PermissionsQuery> {
+    let analysis =
+      catch_analysis_panic(tcx, body_id, AnalysisStage::Permissions, || {
+        Self::new(tcx, body_id)
+      })?;
+    catch_analysis_panic(tcx, body_id, AnalysisStage::Permissions, || {
+      analysis.query_permissions(position, path)
+    })?
+  }
+
+  /// The permissions of `path` right before the innermost expression
+  /// containing `position` is evaluated.
+  ///
+  /// The `path` is a variable, followed by any number of field accesses,
+  /// and optionally preceded by dereferences, e.g. `*p.0.field`. Fields
+  /// of references and boxes are dereferenced automatically.
+  pub fn query_permissions(
+    &self,
+    position: CharPos,
+    path: &str,
+  ) -> AquascopeResult<PermissionsQuery> {
+    let ctxt = &self.permissions;
+    let (hir_id, span) = self.expr_at(position).ok_or_else(|| {
+      invalid_query(format!(
+        "no expression at {}:{}",
+        position.line + 1,
+        position.column + 1
+      ))
+    })?;
+
+    let location = self.location_before(hir_id).ok_or_else(|| {
+      invalid_query(format!(
+        "the expression at {}:{} is not evaluated",
+        position.line + 1,
+        position.column + 1
+      ))
+    })?;
+
+    let place = self.resolve_path(path, location)?;
+    let path_index = ctxt.try_place_to_path(&place).ok_or_else(|| {
+      invalid_query(format!("`{path}` is not a path tracked by the analysis"))
+    })?;
+
+    let point = ctxt.location_to_point(location);
+    let data = ctxt.permissions_data_at_point(path_index, point);
+
+    let mut refiners = Vec::<Refiner>::new();
+    let loans = [
+      data.loan_read_refined,
+      data.loan_write_refined,
+      data.loan_drop_refined,
+    ];
+    for loan in loans.into_iter().flatten() {
+      if !refiners.contains(&Refiner::Loan(loan)) {
+        refiners.push(Refiner::Loan(loan));
+      }
+    }
+    refiners.extend(data.path_moved.map(Refiner::Move));
+
+    let body_span = ctxt.body_with_facts.body.span;
+    let refiners = refiners
+      .into_iter()
+      .map(|refiner| {
+        let (refined_at, explanation) = match refiner {
+          Refiner::Loan(key) => (
+            ctxt.borrow_set[Loan::from_u32(*key)].reserve_location(),
+            self.explain_loan(key, location),
+          ),
+          Refiner::Move(key) => {
+            (ctxt.move_data.moves[Move::from_u32(key.0)].source, None)
+          }
+        };
+        let span = ctxt.location_to_span(refined_at);
+        let span = span.as_local(body_span).unwrap_or(span);
+        RefinerPoint {
+          refiner,
+          range: self.span_to_range(span),
+          explanation,
+        }
+      })
+      .collect();
+
+    Ok(PermissionsQuery {
+      path: path.to_string(),
+      range: self.span_to_range(span),
+      permissions: data.permissions(),
+      data,
+      refiners,
+    })
+  }
+
+  /// The innermost expression of the body containing `position`.
+  fn expr_at(&self, position: CharPos) -> Option<(HirId, Span)> {
+    let tcx = self.permissions.tcx;
+    let mut finder = ExprFinder {
+      tcx,
+      position,
+      found: None,
+    };
+    finder.visit_body(tcx.hir().body(self.permissions.body_id));
+    finder.found
+  }
+
+  /// The first MIR location of `hir_id`, or of its closest parent
+  /// if the expression itself has none (e.g., paths used as places).
+  fn location_before(&self, hir_id: HirId) -> Option<Location> {
+    let hir = self.permissions.tcx.hir();
+    std::iter::once(hir_id)
+      .chain(hir.parent_id_iter(hir_id))
+      .find_map(|id| {
+        self
+          .ir_mapper
+          .get_mir_locations(id, GatherDepth::Nested)?
+          .entry_location()
+      })
+  }
+
+  /// Resolve a path expression to a place, the variable is the one in
+  /// scope at `location`, i.e., declared in the innermost source scope
+  /// containing it.
+  fn resolve_path(
+    &self,
+    path: &str,
+    location: Location,
+  ) -> AquascopeResult<Place<'tcx>> {
+    let tcx = self.permissions.tcx;
+    let body = &self.permissions.body_with_facts.body;
+
+    let path = path.trim();
+    let derefs = path.chars().take_while(|c| *c == '*').count();
+    let mut segments = path[derefs ..].split('.').map(str::trim);
+    let name = segments.next().unwrap_or_default();
+
+    // The scopes visible at `location`, innermost first.
+    let scopes =
+      std::iter::successors(Some(body.source_info(location).scope), |scope| {
+        body.source_scopes[*scope].parent_scope
+      })
+      .collect::<Vec<_>>();
+
+    let mut place = body
+      .var_debug_info
+      .iter()
+      .filter(|info| info.name.as_str() == name)
+      .filter_map(|info| {
+        let depth = scopes.iter().position(|s| *s == info.source_info.scope)?;
+        Some((depth, info))
+      })
+      .min_by_key(|(depth, _)| *depth)
+      .and_then(|(_, info)| match info.value {
+        VarDebugInfoContents::Place(place) => Some(place),
+        VarDebugInfoContents::Const(_) => None,
+      })
+      .ok_or_else(|| invalid_query(format!("unknown variable `{name}`")))?;
+
+    for field in segments {
+      let mut ty = place.ty(body, tcx).ty;
+      while let Some(inner) = ty.builtin_deref(false) {
+        place = place.project_deeper(&[ProjectionElem::Deref], tcx);
+        ty = inner;
+      }
+
+      let (index, field_ty) = field_of(tcx, ty, field).ok_or_else(|| {
+        invalid_query(format!("`{ty}` has no field `{field}`"))
+      })?;
+      place =
+        place.project_deeper(&[ProjectionElem::Field(index, field_ty)], tcx);
+    }
+
+    for _ in 0 .. derefs {
+      if place.ty(body, tcx).ty.builtin_deref(true).is_none() {
+        return Err(invalid_query(format!("`{path}` cannot be dereferenced")));
+      }
+      place = place.project_deeper(&[ProjectionElem::Deref], tcx);
+    }
+
+    Ok(place)
+  }
+}
+
+fn field_of<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  ty: Ty<'tcx>,
+  field: &str,
+) -> Option<(FieldIdx, Ty<'tcx>)> {
+  match ty.kind() {
+    ty::Tuple(tys) => {
+      let index = field.parse::<usize>().ok()?;
+      Some((FieldIdx::from_usize(index), tys.get(index).copied()?))
+    }
+    ty::Adt(adt, args) if adt.is_struct() => adt
+      .non_enum_variant()
+      .fields
+      .iter_enumerated()
+      .find(|(_, def)| def.name.as_str() == field)
+      .map(|(index, def)| (index, def.ty(tcx, args))),
+    _ => None,
+  }
+}
+
+struct ExprFinder<'tcx> {
+  tcx: TyCtxt<'tcx>,
+  position: CharPos,
+  found: Option<(HirId, Span)>,
+}
+
+impl<'tcx> Visitor<'tcx> for ExprFinder<'tcx> {
+  fn visit_expr(&mut self, expr: &'tcx Expr<'tcx>) {
+    let source_map = self.tcx.sess.source_map();
+    let contains = !expr.span.from_expansion()
+      && CharRange::from_span(expr.span, source_map).is_ok_and(|range| {
+        range.start <= self.position && self.position < range.end
+      });
+
+    if contains {
+      let is_smaller = self.found.is_none_or(|(_, span)| {
+        expr.span.hi() - expr.span.lo() <= span.hi() - span.lo()
+      });
+      if is_smaller {
+        self.found = Some((expr.hir_id, expr.span));
+      }
+    }
+
+    intravisit::walk_expr(self, expr);
+  }
+}
@@ -0,0 +1,307 @@
+//! Exporting the region flow graph of a body.
+//!
+//! Flow boundaries only report the first invalid flow at a usage. The
+//! region graph instead contains every flow between the origins of a
+//! body, i.e., the `subset_base` constraints, classified by
+//! [`RegionFlows::flow_kind`](super::permissions::flow::RegionFlows).
+//! It can be exported as JSON, or as DOT for rendering with Graphviz.
+
+use std::fmt::Write;
+
+use rustc_borrowck::consumers::PoloniusRegionVid;
+use rustc_data_structures::fx::{FxHashMap as HashMap, FxHashSet as HashSet};
+use rustc_hir::BodyId;
+use rustc_index::Idx;
+use rustc_middle::{mir::VarDebugInfoContents, ty::TyCtxt};
+use rustc_utils::{source_map::range::CharRange, BodyExt, PlaceExt, SpanExt};
+use serde::Serialize;
+use ts_rs::TS;
+
+use super::{
+  catch_analysis_panic,
+  permissions::{flow::FlowEdgeKind, Origin, Point},
+  AnalysisStage, AquascopeAnalysis, AquascopeResult,
+};
+
+/// The flows between all origins of a body.
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct RegionGraph {
+  pub regions: Vec<RegionNode>,
+  pub flows: Vec<RegionFlowEdge>,
+}
+
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct RegionNode {
+  pub origin: String,
+  /// The strongly connected component of the origin, all origins of a
+  /// component flow into each other.
+  pub scc: usize,
+  /// The borrow or variable the origin belongs to, if any.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub label: Option<String>,
+  /// Is the origin equivalent to a lifetime parameter of the body?
+  pub is_abstract: bool,
+  /// Is the origin equivalent to the borrow of a local?
+  pub is_local: bool,
+}
+
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct RegionFlowEdge {
+  pub from: String,
+  pub to: String,
+  pub kind: FlowEdgeKind,
+  /// Where the flow occurs.
+  pub ranges: Vec<CharRange>,
+}
+
+impl RegionGraph {
+  /// The graph in the DOT language, named `name`. Abstract origins are
+  /// drawn as boxes, local ones are filled, and invalid flows are red.
+  pub fn to_dot(&self, name: &str) -> String {
+    let mut dot = format!("digraph \"{}\" {{\n", escape(name));
+    dot.push_str("  node [shape=ellipse, fontname=monospace];\n");
+
+    let mut sccs = HashMap::<usize, Vec<&RegionNode>>::default();
+    for region in &self.regions {
+      sccs.entry(region.scc).or_default().push(region);
+    }
+    let mut sccs = sccs.into_iter().collect::<Vec<_>>();
+    sccs.sort_by_key(|(scc, _)| *scc);
+
+    for (scc, regions) in sccs {
+      let clustered = regions.len() > 1;
+      let indent = if clustered {
+        writeln!(dot, "  subgraph cluster_{scc} {{\n    style=dashed;")
+          .unwrap();
+        "    "
+      } else {
+        "  "
+      };
+      for region in regions {
+        let mut label = region.origin.clone();
+        if let Some(l) = &region.label {
+          label.push_str("\\n");
+          label.push_str(&escape(l));
+        }
+        let mut attrs = vec![format!("label=\"{label}\"")];
+        if region.is_abstract {
+          attrs.push(String::from("shape=box"));
+        }
+        if region.is_local {
+          attrs.push(String::from("style=filled, fillcolor=\"#fff3c4\""));
+        }
+        writeln!(
+          dot,
+          "{indent}\"{}\" [{}];",
+          escape(&region.origin),
+          attrs.join(", ")
+        )
+        .unwrap();
+      }
+      if clustered {
+        dot.push_str("  }\n");
+      }
+    }
+
+    for flow in &self.flows {
+      let attrs = if flow.kind.is_valid_flow() {
+        String::new()
+      } else {
+        format!(" [color=red, penwidth=2, label=\"{:?}\"]", flow.kind)
+      };
+      writeln!(
+        dot,
+        "  \"{}\" -> \"{}\"{attrs};",
+        escape(&flow.from),
+        escape(&flow.to)
+      )
+      .unwrap();
+    }
+
+    dot.push_str("}\n");
+    dot
+  }
+}
+
+fn escape(s: &str) -> String {
+  s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl<'tcx> AquascopeAnalysis<'tcx> {
+  /// Analyze the body of `body_id` and build its [`RegionGraph`]. Flow
+  /// permissions must be enabled.
+  pub fn region_graph(
+    tcx: TyCtxt<'tcx>,
+    body_id: BodyId,
+  ) -> AquascopeResult<RegionGraph> {
+    catch_analysis_panic(tcx, body_id, AnalysisStage::Permissions, || {
+      Self::new(tcx, body_id).build_region_graph()
+    })
+  }
+
+  fn build_region_graph(&self) -> RegionGraph {
+    let ctxt = &self.permissions;
+    let region_flows = ctxt.region_flows();
+
+    let mut edges = HashMap::<(Origin, Origin), Vec<Point>>::default();
+    for &(from, to, point) in &ctxt.polonius_input_facts.subset_base {
+      if from != to && !ctxt.is_universal_subset((from, to)) {
+        edges.entry((from, to)).or_default().push(point);
+      }
+    }
+
+    let origins = edges
+      .keys()
+      .flat_map(|&(from, to)| [from, to])
+      .collect::<HashSet<_>>();
+    let mut origins = origins.into_iter().collect::<Vec<_>>();
+    origins.sort_by_key(|origin| origin.index());
+
+    let labels = self.origin_labels();
+    let regions = origins
+      .into_iter()
+      .map(|origin| RegionNode {
+        origin: origin_name(origin),
+        scc: region_flows.scc(origin).index(),
+        label: labels.get(&origin).cloned(),
+        is_abstract: region_flows.is_abstract_member(origin),
+        is_local: region_flows.is_local_member(origin),
+      })
+      .collect();
+
+    let mut edges = edges.into_iter().collect::<Vec<_>>();
+    edges.sort_by_key(|((from, to), _)| (from.index(), to.index()));
+    let flows = edges
+      .into_iter()
+      .map(|((from, to), points)| {
+        let mut ranges = Vec::<CharRange>::new();
+        for point in points {
+          let span = ctxt.location_to_span(ctxt.point_to_location(point));
+          let span = span
+            .as_local(ctxt.body_with_facts.body.span)
+            .unwrap_or(span);
+          let range = self.span_to_range(span);
+          if !ranges.contains(&range) {
+            ranges.push(range);
+          }
+        }
+        RegionFlowEdge {
+          from: origin_name(from),
+          to: origin_name(to),
+          kind: region_flows.flow_kind(from, to),
+          ranges,
+        }
+      })
+      .collect();
+
+    RegionGraph { regions, flows }
+  }
+
+  /// Name origins by the borrows issuing into them, the variables whose
+  /// types contain them, or the return type.
+  fn origin_labels(&self) -> HashMap<Origin, String> {
+    let ctxt = &self.permissions;
+    let tcx = ctxt.tcx;
+    let body = &ctxt.body_with_facts.body;
+    let facts = ctxt.polonius_input_facts;
+    let mut labels = HashMap::default();
+
+    for (_, borrow) in ctxt.borrow_set.location_map() {
+      let place = borrow.borrowed_place();
+      if let Some(name) = place.to_string(tcx, body) {
+        let origin = PoloniusRegionVid::from(borrow.region());
+        let prefix = if ctxt.is_mutable_borrow(borrow) {
+          "&mut "
+        } else {
+          "&"
+        };
+        labels
+          .entry(origin)
+          .or_insert_with(|| format!("{prefix}{name}"));
+      }
+    }
+
+    let names = body
+      .var_debug_info
+      .iter()
+      .filter_map(|info| match info.value {
+        VarDebugInfoContents::Place(place) if place.projection.is_empty() => {
+          Some((place.local, info.name.to_string()))
+        }
+        _ => None,
+      })
+      .collect::<HashMap<_, _>>();
+    let mut uses = facts.use_of_var_derefs_origin.clone();
+    uses.sort_by_key(|(local, origin)| (origin.index(), local.as_usize()));
+    for (local, origin) in uses {
+      if let Some(name) = names.get(&local) {
+        labels.entry(origin).or_insert_with(|| name.clone());
+      }
+    }
+
+    for region in body.regions_in_return() {
+      let origin = PoloniusRegionVid::from(region.as_var());
+      labels
+        .entry(origin)
+        .or_insert_with(|| String::from("return type"));
+    }
+
+    labels
+  }
+}
+
+fn origin_name(origin: Origin) -> String {
+  format!("'?{}", origin.index())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn region(origin: &str, scc: usize, label: Option<&str>) -> RegionNode {
+    RegionNode {
+      origin: origin.to_string(),
+      scc,
+      label: label.map(String::from),
+      is_abstract: false,
+      is_local: false,
+    }
+  }
+
+  #[test]
+  fn test_to_dot() {
+    let graph = RegionGraph {
+      regions: vec![
+        RegionNode {
+          is_local: true,
+          ..region("'?1", 0, Some("&v"))
+        },
+        RegionNode {
+          is_abstract: true,
+          ..region("'?2", 1, Some("return type"))
+        },
+      ],
+      flows: vec![RegionFlowEdge {
+        from: String::from("'?1"),
+        to: String::from("'?2"),
+        kind: FlowEdgeKind::LocalOutlivesUniversal,
+        ranges: Vec::new(),
+      }],
+    };
+    let dot = graph.to_dot("f");
+    assert!(dot.starts_with("digraph \"f\" {\n"));
+    assert!(dot.contains(
+      "  \"'?1\" [label=\"'?1\\n&v\", style=filled, fillcolor=\"#fff3c4\"];\n"
+    ));
+    assert!(
+      dot.contains("  \"'?2\" [label=\"'?2\\nreturn type\", shape=box];\n")
+    );
+    assert!(dot.contains(
+      "  \"'?1\" -> \"'?2\" [color=red, penwidth=2, label=\"LocalOutlivesUniversal\"];\n"
+    ));
+    assert!(!dot.contains("cluster"));
+  }
+}
@@ -189,15 +189,20 @@ impl<'a, 'tcx: 'a> HirStepPoints<'a, 'tcx> {
     Self::process_error(&self.fatal_errors)
   }
 
-  pub(super) fn finalize(
+  /// Freeze the gathered segments and build the output of the stepper
+  /// with `build`, given the body's start location and span.
+  pub(super) fn finalize<T>(
     self,
     analysis: &AquascopeAnalysis<'tcx>,
-    mode: PermIncludeMode,
-  ) -> Result<Vec<PermissionsLineDisplay>> {
+    build: impl FnOnce(&TableBuilder<'_, 'tcx>, Location, Span) -> T,
+  ) -> Result<T> {
     let body_hir_id = self.body_value_id();
     let body_span = self.span_of(body_hir_id);
 
-    let mir_segments = self.mir_segments.freeze()?;
+    let mir_segments = self
+      .mir_segments
+      .freeze()
+      .map_err(|e| AquascopeError::StepperError { msg: e.to_string() })?;
 
     log::debug!(
       "Steps analysis found these steps: {:#?}",
@@ -211,7 +216,7 @@ impl<'a, 'tcx: 'a> HirStepPoints<'a, 'tcx> {
       locals_at_scope: self.locals_at_scope,
     };
 
-    Ok(finalizer.finalize_body(self.start_loc, body_span, mode))
+    Ok(build(&finalizer, self.start_loc, body_span))
   }
 
   // Used for tracking path hints of the current branches.
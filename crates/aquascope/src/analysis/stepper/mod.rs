@@ -4,10 +4,11 @@ mod hir_steps;
 #[allow(clippy::similar_names)]
 mod segmented_mir;
 mod table_builder;
+mod timeline;
 
 use std::collections::hash_map::Entry;
 
-use anyhow::{bail, Result};
+use anyhow::Result;
 use fluid_let::fluid_let;
 use rustc_data_structures::{self, fx::FxHashMap as HashMap};
 use rustc_hir::intravisit::Visitor as HirVisitor;
@@ -21,11 +22,15 @@ use crate::analysis::{
   permissions::{
     Permissions, PermissionsCtxt, PermissionsData, PermissionsDomain,
   },
-  AquascopeAnalysis, LoanKey, MoveKey,
+  AquascopeAnalysis, AquascopeError, LoanKey, MoveKey,
 };
 
 fluid_let!(pub static INCLUDE_MODE: PermIncludeMode);
 
+/// Whether to compute the [`PermissionsTimeline`] of each body,
+/// disabled by default.
+fluid_let!(pub static ENABLE_TIMELINE: bool);
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Hash)]
 pub enum PermIncludeMode {
   Changes,
@@ -66,6 +71,40 @@ pub struct PermissionsLineDisplay {
   pub state: Vec<PermissionsStepTable>,
 }
 
+/// The complete permissions of every source-visible place at each step
+/// of a body, in source order.
+///
+/// In contrast to [`PermissionsLineDisplay`], which only shows the
+/// differences between steps, the timeline shows the state of a place
+/// at every step, including those where it doesn't change.
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct PermissionsTimeline {
+  /// The source-visible places of the body.
+  pub places: Vec<String>,
+  pub steps: Vec<TimelineStep>,
+}
+
+/// The state of all places after a single step.
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct TimelineStep {
+  pub location: CharRange,
+  pub state: Vec<(String, TimelineEntry)>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct TimelineEntry {
+  pub permissions: Permissions,
+  pub data: PermissionsData,
+  /// Are the flows made by the step into or out of the regions of the
+  /// place valid? Only present when flow permissions are enabled and the
+  /// step makes such flows.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub flow: Option<bool>,
+}
+
 pub trait Stepable:
   Copy
   + Clone
@@ -342,6 +381,40 @@ pub fn compute_permission_steps(
   analysis: &AquascopeAnalysis<'_>,
 ) -> Result<Vec<PermissionsLineDisplay>> {
   let mode = INCLUDE_MODE.copied().unwrap_or(PermIncludeMode::Changes);
+  step_points(analysis)?.finalize(analysis, |builder, start_loc, body_span| {
+    builder.finalize_body(builder.collect_tables(), start_loc, body_span, mode)
+  })
+}
+
+/// Compute the [`PermissionsTimeline`] of the body, see [`ENABLE_TIMELINE`].
+pub fn compute_permission_timeline(
+  analysis: &AquascopeAnalysis<'_>,
+) -> Result<PermissionsTimeline> {
+  step_points(analysis)?.finalize(analysis, |builder, start_loc, body_span| {
+    builder.finalize_timeline(&builder.collect_tables(), start_loc, body_span)
+  })
+}
+
+/// Compute the steps of the body and, if [`ENABLE_TIMELINE`] is set, its
+/// timeline from the same tables, such that the stepper only runs once.
+pub fn compute_permission_steps_and_timeline(
+  analysis: &AquascopeAnalysis<'_>,
+) -> Result<(Vec<PermissionsLineDisplay>, Option<PermissionsTimeline>)> {
+  let mode = INCLUDE_MODE.copied().unwrap_or(PermIncludeMode::Changes);
+  let with_timeline = ENABLE_TIMELINE.copied().unwrap_or(false);
+  step_points(analysis)?.finalize(analysis, |builder, start_loc, body_span| {
+    let tables = builder.collect_tables();
+    let timeline = with_timeline
+      .then(|| builder.finalize_timeline(&tables, start_loc, body_span));
+    let steps = builder.finalize_body(tables, start_loc, body_span, mode);
+    (steps, timeline)
+  })
+}
+
+/// Split the body's MIR into the segments between source-level steps.
+fn step_points<'a, 'tcx>(
+  analysis: &'a AquascopeAnalysis<'tcx>,
+) -> Result<hir_steps::HirStepPoints<'a, 'tcx>> {
   let ctxt = &analysis.permissions;
   let ir_mapper = &analysis.ir_mapper;
   let body = &ctxt.body_with_facts.body;
@@ -351,12 +424,12 @@ pub fn compute_permission_steps(
   hir_visitor.visit_nested_body(ctxt.body_id);
 
   if let Some(msg) = hir_visitor.get_unsupported_feature() {
-    bail!(msg);
+    return Err(AquascopeError::UnsupportedFeature { msg, range: None }.into());
   }
 
-  if let Some(fatal_error) = hir_visitor.get_internal_error() {
-    bail!(fatal_error);
+  if let Some(msg) = hir_visitor.get_internal_error() {
+    return Err(AquascopeError::StepperError { msg }.into());
   }
 
-  hir_visitor.finalize(analysis, mode)
+  Ok(hir_visitor)
 }
@@ -14,9 +14,9 @@ use crate::{analysis::permissions::PermissionsCtxt, errors};
 /// A single unprocessed table, mapping Places to their differences for a MirSegment.
 #[derive(Debug)]
 pub(super) struct Table<'tcx> {
-  span: Span,
-  segment: MirSegment,
-  data: HashMap<Place<'tcx>, PermissionsDataDiff>,
+  pub(super) span: Span,
+  pub(super) segment: MirSegment,
+  pub(super) data: HashMap<Place<'tcx>, PermissionsDataDiff>,
 }
 
 /// A series of tables, identified by the _ending location_ of the step.
@@ -38,8 +38,16 @@ pub(super) struct TableBuilder<'a, 'tcx: 'a> {
 
 #[allow(clippy::similar_names)]
 impl<'a, 'tcx: 'a> TableBuilder<'a, 'tcx> {
+  /// The unprocessed tables of every step in the body.
+  pub(super) fn collect_tables(&self) -> Tables<'tcx> {
+    let mut tables = Tables::default();
+    self.insert_collection(&mut tables, self.mir.first_collection);
+    tables
+  }
+
   pub(super) fn finalize_body(
     &self,
+    mut diffs: Tables<'tcx>,
     start_loc: Location,
     body_span: Span,
     mode: PermIncludeMode,
@@ -53,19 +61,15 @@ impl<'a, 'tcx: 'a> TableBuilder<'a, 'tcx> {
     // special case this, and show that they "come alive" at the opening brace.
     let first_diff = empty_domain.diff(first_domain);
 
-    // Insert a segment into a table filtering defined places.
-    let mut diffs = Tables::default();
-
     // We do an unchecked insert here to avoid
     // the segment from getting filtered because the
     // segment from and to locations are equal.
     let seg = MirSegment::new(start_loc, start_loc);
-    diffs.entry(seg.to).or_default().push(Table {
+    diffs.entry(seg.to).or_default().insert(0, Table {
       segment: seg,
       span: body_open_brace,
       data: first_diff,
     });
-    self.insert_collection(&mut diffs, self.mir.first_collection);
 
     prettify_permission_steps(self.analysis, diffs, mode)
   }
@@ -80,7 +84,11 @@ impl<'a, 'tcx: 'a> TableBuilder<'a, 'tcx> {
       .collect::<HashSet<_>>()
   }
 
-  fn insert_collection(&self, result: &mut Tables<'tcx>, cid: CollectionId) {
+  pub(super) fn insert_collection(
+    &self,
+    result: &mut Tables<'tcx>,
+    cid: CollectionId,
+  ) {
     let collection = self.mir.get_collection(cid);
 
     for &part in collection.data.iter() {
@@ -223,7 +231,7 @@ pub(super) fn prettify_permission_steps<'tcx>(
   }
 
   let first_error_span_opt =
-    errors::get_span_of_first_error(ctxt.def_id.expect_local())
+    errors::get_span_of_first_error(ctxt.tcx, ctxt.def_id.expect_local())
       .and_then(|s| s.as_local(ctxt.body_with_facts.body.span));
   let source_map = tcx.sess.source_map();
 
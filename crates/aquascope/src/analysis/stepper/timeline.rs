@@ -0,0 +1,212 @@
+//! Build the complete permissions of every place at each step of a body.
+//!
+//! The steps of the timeline are those of the stepper, one for each of its
+//! tables. Steps on the same line share their location, and are ordered
+//! as they occur on the line.
+
+use rustc_borrowck::consumers::PoloniusRegionVid;
+use rustc_data_structures::fx::FxHashSet as HashSet;
+use rustc_middle::mir::{Location, Place};
+use rustc_span::Span;
+use rustc_utils::{PlaceExt, SpanExt};
+
+use super::{table_builder::*, *};
+use crate::{
+  analysis::permissions::{
+    Origin, Point, ENABLE_FLOW_DEFAULT, ENABLE_FLOW_PERMISSIONS,
+  },
+  errors,
+};
+
+impl<'a, 'tcx: 'a> TableBuilder<'a, 'tcx> {
+  pub(super) fn finalize_timeline(
+    &self,
+    tables: &Tables<'tcx>,
+    start_loc: Location,
+    body_span: Span,
+  ) -> PermissionsTimeline {
+    let ctxt = self.ctxt;
+    let tcx = ctxt.tcx;
+    let body = &ctxt.body_with_facts.body;
+    let source_map = tcx.sess.source_map();
+
+    // As in `prettify_permission_steps`, steps are attached to the end of
+    // their line, and tables without visible changes or following the first
+    // error are dropped.
+    let first_error_span_opt =
+      errors::get_span_of_first_error(tcx, ctxt.def_id.expect_local())
+        .and_then(|s| s.as_local(body.span));
+    let mut steps = tables
+      .values()
+      .flatten()
+      .filter(|Table { span, data, .. }| {
+        data.iter().any(|(place, diff)| {
+          place.is_source_visible(tcx, body) && !diff.is_empty()
+        }) && first_error_span_opt.is_none_or(|err| span.lo() <= err.hi())
+      })
+      .map(|Table { segment, span, .. }| (*span, *segment))
+      .collect::<Vec<_>>();
+    steps.sort_by_key(|(span, _)| (span.hi(), span.lo()));
+
+    // The parameters of the body are shown at the opening brace.
+    let first = (
+      body_span.shrink_to_lo(),
+      MirSegment::new(start_loc, start_loc),
+    );
+
+    let mut places = ctxt
+      .domain_places()
+      .into_iter()
+      .filter(|place| place.is_source_visible(tcx, body))
+      .collect::<Vec<_>>();
+    places
+      .sort_by_key(|place| (place.local.as_usize(), place.projection.len()));
+    let names = places
+      .iter()
+      .map(|place| {
+        place
+          .to_string(tcx, body)
+          .unwrap_or_else(|| String::from("<var>"))
+      })
+      .collect::<Vec<_>>();
+
+    let steps = std::iter::once(first)
+      .chain(steps)
+      .map(|(span, segment)| {
+        let span = source_map.span_extend_to_line(span).shrink_to_hi();
+        let point = ctxt.location_to_point(segment.to);
+        let flows = self.flows_in_segment(segment);
+        let state = places
+          .iter()
+          .zip(&names)
+          .map(|(place, name)| {
+            let path = ctxt.place_to_path(place);
+            let data = ctxt.permissions_data_at_point(path, point);
+            let entry = TimelineEntry {
+              permissions: data.permissions(),
+              data,
+              flow: self.flow_state(place, flows.as_deref()),
+            };
+            (name.clone(), entry)
+          })
+          .collect();
+        TimelineStep {
+          location: self.analysis.span_to_range(span),
+          state,
+        }
+      })
+      .collect();
+
+    PermissionsTimeline {
+      places: names,
+      steps,
+    }
+  }
+
+  /// The local flows created within `segment`, if flow permissions are enabled.
+  ///
+  /// A segment doesn't record all locations between its endpoints, so only
+  /// the locations leading up to its end within the same block are used.
+  fn flows_in_segment(
+    &self,
+    segment: MirSegment,
+  ) -> Option<Vec<(Origin, Origin)>> {
+    if !ENABLE_FLOW_PERMISSIONS
+      .copied()
+      .unwrap_or(ENABLE_FLOW_DEFAULT)
+    {
+      return None;
+    }
+
+    let ctxt = self.ctxt;
+    let to = segment.to;
+    let first = if segment.from.block == to.block {
+      segment.from.statement_index
+    } else {
+      0
+    };
+    let points = (first ..= to.statement_index)
+      .flat_map(|statement_index| {
+        ctxt.location_to_points(Location {
+          block: to.block,
+          statement_index,
+        })
+      })
+      .collect::<HashSet<Point>>();
+
+    let flows = ctxt
+      .polonius_input_facts
+      .subset_base
+      .iter()
+      .filter(|&&(f, t, p)| {
+        !ctxt.is_universal_subset((f, t)) && points.contains(&p)
+      })
+      .map(|&(f, t, _)| (f, t))
+      .collect();
+    Some(flows)
+  }
+
+  /// Are the `flows` involving the regions of `place` all valid?
+  fn flow_state(
+    &self,
+    place: &Place<'tcx>,
+    flows: Option<&[(Origin, Origin)]>,
+  ) -> Option<bool> {
+    let flows = flows?;
+    let ctxt = self.ctxt;
+    let body = &ctxt.body_with_facts.body;
+    let origins = place
+      .ty(body, ctxt.tcx)
+      .ty
+      .walk()
+      .filter_map(|arg| arg.as_region())
+      .filter(|region| region.is_var())
+      .map(|region| PoloniusRegionVid::from(region.as_var()))
+      .collect::<HashSet<_>>();
+
+    let region_flows = ctxt.region_flows();
+    let mut involved = flows
+      .iter()
+      .filter(|(f, t)| origins.contains(f) || origins.contains(t))
+      .peekable();
+    involved.peek()?;
+    Some(involved.all(|&(f, t)| region_flows.flow_kind(f, t).is_valid_flow()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{analysis::AquascopeAnalysis, test_utils as tu};
+
+  #[test]
+  fn steps_on_one_line() {
+    let code = r#"
+fn test() {
+  let mut s = String::new();
+  let r = &mut s; r.push_str("a");
+  s.push_str("b");
+}
+"#;
+    tu::compile_normal(code, |tcx| {
+      tu::for_each_body(tcx, |body_id, _| {
+        let analysis = AquascopeAnalysis::new(tcx, body_id);
+        let timeline = compute_permission_timeline(&analysis).unwrap();
+
+        // Each statement on the line of `r` is its own step, such that the
+        // borrow of `s` ends between them.
+        let writes = timeline
+          .steps
+          .iter()
+          .filter(|step| step.location.start.line == 3)
+          .map(|step| {
+            let (_, entry) =
+              step.state.iter().find(|(name, _)| name == "s").unwrap();
+            entry.permissions.write
+          })
+          .collect::<Vec<_>>();
+        assert_eq!(writes, vec![false, true]);
+      })
+    })
+  }
+}
@@ -2,29 +2,124 @@ pub mod silent;
 
 use std::cell::RefCell;
 
-use rustc_errors::{DiagInner, TRACK_DIAGNOSTIC};
+use rustc_data_structures::{fx::FxIndexMap as IndexMap, sync::Lrc};
+use rustc_driver::DEFAULT_LOCALE_RESOURCES;
+use rustc_errors::{
+  fallback_fluent_bundle,
+  translation::{to_fluent_args, Translate},
+  DiagInner, FluentBundle, LazyFallbackBundle, TRACK_DIAGNOSTIC,
+};
 use rustc_hir::def_id::LocalDefId;
+use rustc_middle::ty::TyCtxt;
 use rustc_span::Span;
 
 thread_local! {
-    static BODY_DIAGNOSTICS: RefCell<Vec<DiagnosticInfo>> = RefCell::new(Vec::default());
+    static BODY_DIAGNOSTICS: RefCell<DiagnosticStore> = RefCell::new(DiagnosticStore::default());
     static CURRENT_BODY: RefCell<Option<LocalDefId>> = const { RefCell::new(None) };
+    static TRANSLATOR: DiagnosticTranslator = DiagnosticTranslator {
+      fallback_bundle: fallback_fluent_bundle(DEFAULT_LOCALE_RESOURCES.to_vec(), false),
+    };
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct DiagnosticInfo {
+  pub primary_span: Span,
+  pub is_error: bool,
+  /// The diagnostic level, e.g. `error` or `warning`.
+  pub level: &'static str,
+  /// The rendered (translated) diagnostic message.
+  pub message: String,
+  /// The error code, e.g. `E0502`, if the diagnostic has one.
+  pub code: Option<String>,
+  /// All spans of the diagnostic, primary and secondary, with their labels.
+  pub labels: Vec<DiagnosticLabel>,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct DiagnosticLabel {
+  pub span: Span,
+  pub is_primary: bool,
+  pub label: Option<String>,
+}
+
+/// All diagnostics reported during the analysis of a crate.
+#[derive(Default)]
+struct DiagnosticStore {
+  /// Diagnostics reported while a body was being tracked, in the order
+  /// the bodies first reported one, such that iteration is deterministic.
+  by_body: IndexMap<LocalDefId, Vec<DiagnosticInfo>>,
+  /// Diagnostics reported while no body was tracked, e.g.,
+  /// type-checking errors reported before the first body is analyzed.
+  untracked: Vec<DiagnosticInfo>,
 }
 
-#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
-struct DiagnosticInfo {
-  primary_span: Span,
-  is_error: bool,
+impl DiagnosticStore {
+  fn iter(
+    &self,
+  ) -> impl Iterator<Item = (Option<LocalDefId>, &DiagnosticInfo)> {
+    self
+      .by_body
+      .iter()
+      .flat_map(|(def_id, ds)| ds.iter().map(|d| (Some(*def_id), d)))
+      .chain(self.untracked.iter().map(|d| (None, d)))
+  }
+}
+
+/// Renders diagnostic messages, which are mostly Fluent identifiers, to text.
+struct DiagnosticTranslator {
+  fallback_bundle: LazyFallbackBundle,
+}
+
+impl Translate for DiagnosticTranslator {
+  fn fluent_bundle(&self) -> Option<&Lrc<FluentBundle>> {
+    None
+  }
+
+  fn fallback_fluent_bundle(&self) -> &FluentBundle {
+    &self.fallback_bundle
+  }
 }
 
 fn track_diagnostic<R>(d: DiagInner, f: &mut dyn FnMut(DiagInner) -> R) -> R {
+  let args = to_fluent_args(d.args.iter());
+  let (message, labels) = TRANSLATOR.with(|translator| {
+    let message = translator
+      .translate_messages(&d.messages, &args)
+      .into_owned();
+    let labels = d
+      .span
+      .span_labels()
+      .into_iter()
+      .map(|span_label| DiagnosticLabel {
+        span: span_label.span,
+        is_primary: span_label.is_primary,
+        label: span_label.label.and_then(|label| {
+          translator
+            .translate_message(&label, &args)
+            .ok()
+            .map(|label| label.into_owned())
+        }),
+      })
+      .collect::<Vec<_>>();
+    (message, labels)
+  });
+
+  let current_body = CURRENT_BODY.with(|id| *id.borrow());
   BODY_DIAGNOSTICS.with(|diagnostics| {
     let mut diagnostics = diagnostics.borrow_mut();
+    let store = match current_body {
+      Some(def_id) => diagnostics.by_body.entry(def_id).or_default(),
+      None => &mut diagnostics.untracked,
+    };
     let d = DiagnosticInfo {
       primary_span: d.sort_span,
       is_error: d.is_error(),
+      level: d.level().to_str(),
+      message,
+      code: d.code.map(|code| code.to_string()),
+      labels,
     };
-    diagnostics.push(d);
+    store.push(d);
   });
 
   // We need to actually report the diagnostic with the
@@ -40,47 +135,75 @@ fn track_diagnostic<R>(d: DiagInner, f: &mut dyn FnMut(DiagInner) -> R) -> R {
 pub fn initialize_error_tracking() {
   log::debug!("Track diagnostics updated");
   TRACK_DIAGNOSTIC.swap(&(track_diagnostic as _));
+  CURRENT_BODY.with(|id| id.borrow_mut().take());
+  BODY_DIAGNOSTICS.with(|diagnostics| {
+    *diagnostics.borrow_mut() = DiagnosticStore::default();
+  });
 }
 
 /// Initialize the error tracking for a given routine. It's recommended
 /// to call this on start of every new analysis. In Aquascope, this would
 /// be per-body analyzed.
+///
+/// Diagnostics reported from now on are attributed to `def_id`,
+/// previously reported diagnostics are kept for their own bodies.
 pub fn track_body_diagnostics(def_id: LocalDefId) {
-  // Update the current LocalDefId
   CURRENT_BODY.with(|id| {
     let mut id = id.borrow_mut();
     let old_value = id.replace(def_id);
     log::debug!("Replacing tracked body id {old_value:?} with {def_id:?}");
   });
-  BODY_DIAGNOSTICS.with(|diagnostics| {
-    let mut diagnostics = diagnostics.borrow_mut();
-    // FIXME(gavinleroy): we should really be caching the diagnostics by
-    // LocalDefId, meaning that we don't have to clean them after
-    // each analysis. This also has the added benefic of caching
-    // in case we ever reuse processes for server queries.
-    diagnostics.clear();
-  });
 }
 
-pub fn errors_exist() -> bool {
-  BODY_DIAGNOSTICS.with(|diagnostics| !diagnostics.borrow().is_empty())
+pub fn get_span_of_first_error(
+  tcx: TyCtxt,
+  def_id: LocalDefId,
+) -> Option<Span> {
+  get_first_error(tcx, def_id).map(|d| d.primary_span)
 }
 
-pub fn get_span_of_first_error(def_id: LocalDefId) -> Option<Span> {
-  // A security check that the body expected by the caller is
-  // in sync with that of the error diagnostics.
-  CURRENT_BODY.with(|id| {
-    assert_eq!(def_id, id.borrow().unwrap());
-  });
+/// Returns every diagnostic attributed to the body of `def_id`.
+///
+/// A diagnostic belongs to a body if it was reported while the body was
+/// tracked, or if its primary span lies within the body. The latter
+/// catches diagnostics reported before the body's analysis started,
+/// e.g. during type-checking, or while analyzing an enclosing body.
+pub fn get_body_diagnostics(
+  tcx: TyCtxt,
+  def_id: LocalDefId,
+) -> Vec<DiagnosticInfo> {
+  let body_span = tcx.hir().span_with_body(tcx.local_def_id_to_hir_id(def_id));
+  let within_body = |span: Span| {
+    !span.is_dummy()
+      && (body_span.contains(span)
+        || body_span.contains(span.source_callsite()))
+  };
 
   BODY_DIAGNOSTICS.with(|diagnostics| {
     let diagnostics = diagnostics.borrow();
 
-    log::debug!("Diagnostics {:?}", diagnostics);
+    log::debug!(
+      "Diagnostics for {def_id:?} in {:?}",
+      diagnostics.by_body.keys()
+    );
 
     diagnostics
       .iter()
-      .filter_map(|d| d.is_error.then_some(d.primary_span))
-      .min_by_key(|s| s.lo())
+      .filter(|(owner, d)| {
+        *owner == Some(def_id) || within_body(d.primary_span)
+      })
+      .map(|(_, d)| d.clone())
+      .collect()
   })
 }
+
+/// Returns the error diagnostic which occurs first in the source of the body.
+pub fn get_first_error(
+  tcx: TyCtxt,
+  def_id: LocalDefId,
+) -> Option<DiagnosticInfo> {
+  get_body_diagnostics(tcx, def_id)
+    .into_iter()
+    .filter(|d| d.is_error)
+    .min_by_key(|d| d.primary_span.lo())
+}
@@ -16,10 +16,14 @@ mod miri_utils;
 mod mvalue;
 mod step;
 
-pub use mvalue::MValue;
+pub use mvalue::{
+  Abbreviated, MHeapAllocKind, MMemorySegment, MPath, MPathSegment, MValue,
+};
 use rustc_session::Session;
 use smallvec::SmallVec;
-pub use step::MTrace;
+pub use step::{
+  MFrame, MHeap, MLocal, MResult, MStack, MStep, MTrace, MUndefinedBehavior,
+};
 
 use crate::interpreter::mapper::Mapper;
 
@@ -73,6 +77,8 @@ pub(crate) fn interpret(tcx: TyCtxt) -> Result<MTrace<CharRange>> {
 pub struct InterpretCallbacks {
   should_fail: bool,
   pub result: Option<Result<MTrace<CharRange>>>,
+  /// The source of the file containing `main`, for rendering the trace.
+  pub source: Option<String>,
 }
 
 impl InterpretCallbacks {
@@ -80,6 +86,7 @@ impl InterpretCallbacks {
     InterpretCallbacks {
       should_fail,
       result: None,
+      source: None,
     }
   }
 }
@@ -131,6 +138,12 @@ impl rustc_driver::Callbacks for InterpretCallbacks {
     tcx: TyCtxt<'_>,
   ) -> rustc_driver::Compilation {
     self.result = Some(interpret(tcx));
+    self.source = tcx.entry_fn(()).and_then(|(def_id, _)| {
+      let source_map = tcx.sess.source_map();
+      let source_file =
+        source_map.lookup_source_file(tcx.def_span(def_id).lo());
+      source_file.src.as_ref().map(|src| src.to_string())
+    });
     rustc_driver::Compilation::Stop
   }
 }
@@ -41,8 +41,8 @@ pub enum MPathSegment {
 #[derive(Serialize, Deserialize, Clone, Debug, TS, PartialEq)]
 #[ts(export)]
 pub struct MPath {
-  segment: MMemorySegment,
-  parts: Vec<MPathSegment>,
+  pub segment: MMemorySegment,
+  pub parts: Vec<MPathSegment>,
 }
 
 const ABBREV_MAX: u64 = 12;
@@ -38,9 +38,11 @@ use super::mvalue::{MMemorySegment, MPathSegment, MValue};
 #[derive(Serialize, Debug, TS)]
 #[ts(export)]
 pub struct MLocal {
-  name: String,
-  value: MValue,
-  moved_paths: Vec<Vec<MPathSegment>>,
+  pub name: String,
+  pub value: MValue,
+  /// Paths within the local which were moved out of, the
+  /// empty path if the local as a whole was moved.
+  pub moved_paths: Vec<Vec<MPathSegment>>,
 }
 
 #[derive(Serialize, Debug, TS)]
@@ -27,8 +27,13 @@ use rustc_utils::{
 use crate::{
   analysis::{
     self,
-    boundaries::{compute_permission_boundaries, PermissionsBoundary},
-    permissions::{Permissions, ENABLE_FLOW_PERMISSIONS},
+    boundaries::{
+      compute_permission_boundaries, PermissionsBoundary,
+      ENABLE_IMPLICIT_DROPS, ENABLE_MACRO_TRACING,
+    },
+    permissions::{
+      Permissions, ENABLE_FLOW_PERMISSIONS, ENABLE_INTERIOR_MUTABILITY,
+    },
     stepper::{
       self, compute_permission_steps, PermIncludeMode, PermissionsDataDiff,
     },
@@ -73,6 +78,7 @@ impl From<&str> for Permissions {
       // We keep 'd' for backwards compatibility from a
       // time when the front-end permissions showed 'D' for drop.
       drop: l.contains('d') || l.contains('o'),
+      interior_write: l.contains('i'),
     }
   }
 }
@@ -86,6 +92,9 @@ static CFG_HASH: &str = "////!";
 #[derive(Debug, Default)]
 pub(crate) struct TestFileConfig {
   show_flows: Option<bool>,
+  interior_mutability: Option<bool>,
+  implicit_drops: Option<bool>,
+  trace_macros: Option<bool>,
 }
 
 fn split_test_source(
@@ -205,6 +214,15 @@ pub(crate) fn load_test_from_file(
     if line.starts_with(CFG_HASH) && line.contains("show-flows") {
       cfg.show_flows = Some(true);
     }
+    if line.starts_with(CFG_HASH) && line.contains("interior-mutability") {
+      cfg.interior_mutability = Some(true);
+    }
+    if line.starts_with(CFG_HASH) && line.contains("implicit-drops") {
+      cfg.implicit_drops = Some(true);
+    }
+    if line.starts_with(CFG_HASH) && line.contains("trace-macros") {
+      cfg.trace_macros = Some(true);
+    }
   }
 
   Ok((source, cfg))
@@ -326,6 +344,12 @@ pub fn test_boundaries_in_file(
     compile_normal(source, move |tcx| {
       for_each_body(tcx, |body_id, _body_with_facts| {
         fluid_set!(ENABLE_FLOW_PERMISSIONS, cfg.show_flows.unwrap_or(false));
+        fluid_set!(
+          ENABLE_INTERIOR_MUTABILITY,
+          cfg.interior_mutability.unwrap_or(false)
+        );
+        fluid_set!(ENABLE_IMPLICIT_DROPS, cfg.implicit_drops.unwrap_or(false));
+        fluid_set!(ENABLE_MACRO_TRACING, cfg.trace_macros.unwrap_or(false));
         let ctxt = AquascopeAnalysis::new(tcx, body_id);
         // Required to give the snapshot a more specific internal name.
         let tag = analysis_snapshot_tag(&ctxt);
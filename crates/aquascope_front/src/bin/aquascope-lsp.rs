@@ -0,0 +1,6 @@
+#![feature(rustc_private)]
+
+fn main() -> anyhow::Result<()> {
+  env_logger::init();
+  aquascope_front::lsp::run_server()
+}
@@ -21,6 +21,9 @@ extern crate rustc_mir_dataflow;
 extern crate rustc_serialize;
 extern crate rustc_span;
 
+pub mod lsp;
 pub mod plugin;
+pub mod render;
+mod selector;
 
 pub use plugin::AquascopePlugin;
@@ -0,0 +1,980 @@
+//! A language server showing permissions within editors.
+//!
+//! The server speaks just enough of the Language Server Protocol over
+//! stdio to provide:
+//! - inlay hints with the expected permissions of every boundary,
+//! - hovers with the permissions data of the path under the cursor,
+//! - diagnostics for boundaries whose expected permissions are missing.
+//!
+//! Bodies are analyzed on demand with `cargo aquascope permissions`,
+//! restricted to the body under the cursor for hovers and to the visible
+//! lines for inlay hints. Analyses run in the background, and requests
+//! waiting for one are answered once it finishes. The analysis reads files
+//! from disk, so results are only shown for saved files. They are cached
+//! until the file changes, including hovers outside of any body and
+//! analyses which failed.
+
+use std::{
+  collections::{HashMap, HashSet},
+  io::{self, BufRead, Read, Write},
+  mem,
+  path::{Path, PathBuf},
+  process::Command,
+  sync::mpsc::{self, Sender},
+  thread,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use aquascope::analysis::{
+  boundaries::{ImplicitDrop, PermissionsBoundary},
+  metadata::BodyMetadata,
+  permissions::Permissions,
+};
+use rustc_utils::source_map::range::{CharPos, CharRange};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Error codes defined by JSON-RPC.
+const METHOD_NOT_FOUND: i32 = -32601;
+const INTERNAL_ERROR: i32 = -32603;
+
+/// Serve requests on stdin until the client asks the server to exit.
+pub fn run_server() -> Result<()> {
+  let (events, received) = mpsc::channel();
+
+  // Messages are read on their own thread, such that the results of
+  // analyses are handled as soon as they finish.
+  let messages = events.clone();
+  thread::spawn(move || {
+    let mut input = io::stdin().lock();
+    loop {
+      let message = read_message(&mut input);
+      let last = !matches!(message, Ok(Some(_)));
+      if messages.send(Event::Message(message)).is_err() || last {
+        break;
+      }
+    }
+  });
+
+  let mut output = io::stdout().lock();
+  let mut server = Server::new(events);
+  for event in received {
+    let outgoing = match event {
+      Event::Message(message) => match message? {
+        Some(message) => server.handle(&message),
+        None => break,
+      },
+      Event::Analyzed(analyzed) => server.analyzed(analyzed),
+    };
+    for outgoing in outgoing {
+      write_message(&mut output, &outgoing)?;
+    }
+    if server.exit {
+      break;
+    }
+  }
+  Ok(())
+}
+
+enum Event {
+  /// A message from the client, `None` once it closed the stream.
+  Message(Result<Option<Value>>),
+  Analyzed(Analyzed),
+}
+
+/// The result of an analysis run in the background.
+struct Analyzed {
+  uri: String,
+  version: u64,
+  selector: Selector,
+  bodies: Result<Vec<BodyAnalysis>>,
+}
+
+/// The analysis of one body, as printed by
+/// `cargo aquascope permissions --format ndjson`.
+#[derive(Deserialize)]
+struct BodyAnalysis {
+  meta: BodyMetadata,
+  #[serde(rename = "Ok")]
+  output: Option<Boundaries>,
+}
+
+/// The boundaries of an `AnalysisOutput`, the only part used by the server.
+#[derive(Deserialize)]
+struct Boundaries {
+  boundaries: Vec<PermissionsBoundary>,
+}
+
+fn contains(range: &CharRange, pos: CharPos) -> bool {
+  let key = |pos: CharPos| (pos.line, pos.column);
+  key(range.start) <= key(pos) && key(pos) <= key(range.end)
+}
+
+fn letters(perms: Permissions) -> String {
+  [
+    ("R", perms.read),
+    ("W", perms.write),
+    ("O", perms.drop),
+    ("I", perms.interior_write),
+  ]
+  .into_iter()
+  .filter_map(|(letter, has)| has.then_some(letter))
+  .collect()
+}
+
+/// The expected permissions of `boundary` which it does not have.
+fn missing(boundary: &PermissionsBoundary) -> Permissions {
+  let (expected, actual) = (boundary.expected, boundary.actual);
+  Permissions {
+    read: expected.read && !actual.read,
+    write: expected.write && !actual.write,
+    drop: expected.drop && !actual.drop,
+    interior_write: expected.interior_write && !actual.interior_write,
+  }
+}
+
+fn label(boundary: &PermissionsBoundary) -> String {
+  let mut label = letters(boundary.expected);
+  if let Some(flow) = &boundary.expecting_flow {
+    label.push('F');
+    if flow.is_violation {
+      label.push('!');
+    }
+  }
+  if let Some(unchecked) = &boundary.unchecked {
+    label.push('U');
+    if unchecked.is_violation() {
+      label.push('!');
+    }
+  }
+  if let Some(operator) = &boundary.operator {
+    label.push_str(if operator.mutable { "&mut" } else { "&" });
+  }
+  if boundary.in_macro.is_some() {
+    label.push('M');
+  }
+  if matches!(boundary.implicit_drop, Some(ImplicitDrop::Custom)) {
+    label.push('D');
+  }
+  label
+}
+
+/// The bodies of a document to analyze.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Selector {
+  /// The body containing a line and column.
+  Position(usize, usize),
+  /// The bodies within a range of lines.
+  Lines(usize, usize),
+}
+
+/// A request about a document, which may wait for its analysis.
+enum Request {
+  Hover(CharPos),
+  InlayHints(usize, usize),
+}
+
+impl Request {
+  /// The result of the request if the document can't be analyzed.
+  fn empty(&self) -> Value {
+    match self {
+      Request::Hover(_) => Value::Null,
+      Request::InlayHints(..) => json!([]),
+    }
+  }
+}
+
+struct Pending {
+  id: Value,
+  request: Request,
+}
+
+/// How a request was handled.
+enum Reply {
+  Now(Value),
+  /// The response is sent once the analysis it waits for finishes.
+  Later,
+  Unsupported,
+}
+
+struct Document {
+  path: PathBuf,
+  text: String,
+  /// Does the editor contents differ from the file on disk?
+  dirty: bool,
+  /// Incremented whenever the file changes, to discard the results of
+  /// analyses of a previous version.
+  version: u64,
+  bodies: Vec<BodyAnalysis>,
+  /// Line ranges which were already analyzed for inlay hints.
+  analyzed_lines: Vec<(usize, usize)>,
+  /// Lines where a hover was analyzed without finding a body.
+  missed_lines: HashSet<usize>,
+  /// Why the analysis failed, it is not retried until the file changes.
+  error: Option<String>,
+  /// Selectors being analyzed in the background.
+  running: HashSet<Selector>,
+  pending: Vec<Pending>,
+}
+
+impl Document {
+  fn new(path: PathBuf, text: String) -> Self {
+    Document {
+      path,
+      text,
+      dirty: false,
+      version: 0,
+      bodies: Vec::new(),
+      analyzed_lines: Vec::new(),
+      missed_lines: HashSet::new(),
+      error: None,
+      running: HashSet::new(),
+      pending: Vec::new(),
+    }
+  }
+
+  /// Forget the analyses of the previous version of the file.
+  fn invalidate(&mut self) {
+    self.version += 1;
+    self.bodies.clear();
+    self.analyzed_lines.clear();
+    self.missed_lines.clear();
+    self.error = None;
+    self.running.clear();
+  }
+
+  fn boundaries(&self) -> impl Iterator<Item = &PermissionsBoundary> {
+    self
+      .bodies
+      .iter()
+      .filter_map(|b| b.output.as_ref())
+      .flat_map(|output| &output.boundaries)
+  }
+
+  /// Answer `request` from the cached analyses, or return the selector
+  /// which must be analyzed first.
+  fn resolve(&self, request: &Request) -> Result<Value, Selector> {
+    if self.dirty || self.error.is_some() {
+      return Ok(request.empty());
+    }
+    match *request {
+      Request::Hover(pos) => {
+        let analyzed = self.missed_lines.contains(&pos.line)
+          || self
+            .bodies
+            .iter()
+            .any(|b| contains(&b.meta.body_range, pos));
+        if !analyzed {
+          return Err(Selector::Position(pos.line, pos.column));
+        }
+        Ok(self.hover(pos))
+      }
+      Request::InlayHints(start, end) => {
+        let analyzed = self
+          .analyzed_lines
+          .iter()
+          .any(|(lo, hi)| *lo <= start && end <= *hi);
+        if !analyzed {
+          return Err(Selector::Lines(start, end));
+        }
+        Ok(self.inlay_hints(start, end))
+      }
+    }
+  }
+
+  fn hover(&self, pos: CharPos) -> Value {
+    let text = line_text(&self.text, pos.line);
+    let Some(boundary) = self
+      .boundaries()
+      .filter(|b| {
+        b.location.line == pos.line && b.location.column <= pos.column
+      })
+      .max_by_key(|b| b.location.column)
+      // The boundary is at the start of the path, the hovered text
+      // must belong to the same path.
+      .filter(|b| {
+        !text
+          .chars()
+          .skip(b.location.column)
+          .take(pos.column - b.location.column)
+          .any(char::is_whitespace)
+      })
+    else {
+      return Value::Null;
+    };
+
+    json!({
+      "contents": {"kind": "markdown", "value": hover_markdown(boundary)},
+      "range": word_range(text, boundary.location),
+    })
+  }
+
+  fn inlay_hints(&self, start: usize, end: usize) -> Value {
+    let hints = self
+      .boundaries()
+      .filter(|b| start <= b.location.line && b.location.line <= end)
+      .filter_map(|b| {
+        let label = label(b);
+        if label.is_empty() {
+          return None;
+        }
+        let missing = letters(missing(b));
+        let tooltip = if missing.is_empty() {
+          format!("expected permissions: {}", letters(b.expected))
+        } else {
+          format!("missing permissions: {missing}")
+        };
+        Some(json!({
+          "position": lsp_pos(&self.text, b.location),
+          "label": label,
+          "tooltip": tooltip,
+          "paddingRight": true,
+        }))
+      })
+      .collect::<Vec<_>>();
+    Value::Array(hints)
+  }
+}
+
+struct Server {
+  documents: HashMap<String, Document>,
+  show_flows: bool,
+  exit: bool,
+  /// Where analyses run in the background send their results.
+  events: Sender<Event>,
+}
+
+impl Server {
+  fn new(events: Sender<Event>) -> Self {
+    Server {
+      documents: HashMap::new(),
+      show_flows: false,
+      exit: false,
+      events,
+    }
+  }
+
+  /// Handle one message from the client, returning the messages to send back.
+  fn handle(&mut self, message: &Value) -> Vec<Value> {
+    let Some(method) = message["method"].as_str() else {
+      // A response to a request of the server, which makes none.
+      return Vec::new();
+    };
+    let params = &message["params"];
+
+    match message.get("id") {
+      Some(id) => {
+        let response = match self.request(id, method, params) {
+          Ok(Reply::Now(result)) => response(id, result),
+          Ok(Reply::Later) => return Vec::new(),
+          Ok(Reply::Unsupported) => error_response(
+            id,
+            METHOD_NOT_FOUND,
+            &format!("Unsupported method: {method}"),
+          ),
+          Err(e) => error_response(id, INTERNAL_ERROR, &format!("{e:#}")),
+        };
+        vec![response]
+      }
+      None => self
+        .notification(method, params)
+        .unwrap_or_else(|e| vec![log_message(&e)]),
+    }
+  }
+
+  fn request(
+    &mut self,
+    id: &Value,
+    method: &str,
+    params: &Value,
+  ) -> Result<Reply> {
+    let result = match method {
+      "initialize" => {
+        self.show_flows = params["initializationOptions"]["showFlows"]
+          .as_bool()
+          .unwrap_or(false);
+        json!({
+          "capabilities": {
+            "textDocumentSync": {"openClose": true, "change": 1, "save": true},
+            "hoverProvider": true,
+            "inlayHintProvider": true,
+          },
+          "serverInfo": {"name": "aquascope-lsp", "version": VERSION},
+        })
+      }
+      "shutdown" => Value::Null,
+      "textDocument/hover" => {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let (line, character) = lsp_position(&params["position"])?;
+        let text = line_text(&self.document(uri)?.text, line);
+        let pos = CharPos {
+          line,
+          column: utf16_to_column(text, character),
+        };
+        return self.document_request(uri, id, Request::Hover(pos));
+      }
+      "textDocument/inlayHint" => {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let start = lsp_position(&params["range"]["start"])?;
+        let end = lsp_position(&params["range"]["end"])?;
+        let request = Request::InlayHints(start.0, end.0);
+        return self.document_request(uri, id, request);
+      }
+      _ => return Ok(Reply::Unsupported),
+    };
+    Ok(Reply::Now(result))
+  }
+
+  /// Answer `request` about `uri`, analyzing the document first if needed.
+  fn document_request(
+    &mut self,
+    uri: &str,
+    id: &Value,
+    request: Request,
+  ) -> Result<Reply> {
+    let doc = self
+      .documents
+      .get_mut(uri)
+      .ok_or_else(|| anyhow!("Unknown document: {uri}"))?;
+    match doc.resolve(&request) {
+      Ok(result) => Ok(Reply::Now(result)),
+      Err(selector) => {
+        doc.pending.push(Pending {
+          id: id.clone(),
+          request,
+        });
+        self.analyze(uri, selector);
+        Ok(Reply::Later)
+      }
+    }
+  }
+
+  fn notification(
+    &mut self,
+    method: &str,
+    params: &Value,
+  ) -> Result<Vec<Value>> {
+    let uri = params["textDocument"]["uri"]
+      .as_str()
+      .unwrap_or_default()
+      .to_string();
+    match method {
+      "exit" => self.exit = true,
+      "textDocument/didOpen" => {
+        let text = params["textDocument"]["text"]
+          .as_str()
+          .unwrap_or_default()
+          .to_string();
+        let path = uri_to_path(&uri)?;
+        self.documents.insert(uri, Document::new(path, text));
+      }
+      "textDocument/didChange" => {
+        if let Some(doc) = self.documents.get_mut(&uri) {
+          // Only full document synchronization is supported.
+          if let Some(text) = params["contentChanges"]
+            .as_array()
+            .and_then(|changes| changes.last())
+            .and_then(|change| change["text"].as_str())
+          {
+            doc.text = text.to_string();
+          }
+          doc.dirty = true;
+          doc.invalidate();
+          let mut outgoing = vec![publish_diagnostics(&uri, doc)];
+          outgoing.extend(self.answer_pending(&uri));
+          return Ok(outgoing);
+        }
+      }
+      "textDocument/didSave" => {
+        if let Some(doc) = self.documents.get_mut(&uri) {
+          if let Some(text) = params["text"].as_str() {
+            doc.text = text.to_string();
+          } else {
+            doc.text = std::fs::read_to_string(&doc.path)?;
+          }
+          doc.dirty = false;
+          doc.invalidate();
+          // Diagnostics are published again as the new version is analyzed.
+          let mut outgoing = vec![clear_diagnostics(&uri)];
+          outgoing.extend(self.answer_pending(&uri));
+          return Ok(outgoing);
+        }
+      }
+      "textDocument/didClose" => {
+        let mut outgoing = vec![clear_diagnostics(&uri)];
+        if let Some(doc) = self.documents.remove(&uri) {
+          outgoing.extend(
+            doc
+              .pending
+              .iter()
+              .map(|pending| response(&pending.id, pending.request.empty())),
+          );
+        }
+        return Ok(outgoing);
+      }
+      _ => {}
+    }
+    Ok(Vec::new())
+  }
+
+  /// Analyze the bodies of `uri` selected by `selector` in the background,
+  /// unless they are already being analyzed.
+  fn analyze(&mut self, uri: &str, selector: Selector) {
+    let Some(doc) = self.documents.get_mut(uri) else {
+      return;
+    };
+    if !doc.running.insert(selector) {
+      return;
+    }
+
+    let selector_args = match selector {
+      Selector::Position(line, column) => {
+        let offset = byte_offset(&doc.text, CharPos { line, column });
+        ["--offset".to_string(), offset.to_string()]
+      }
+      Selector::Lines(start, end) => {
+        ["--lines".to_string(), format!("{}-{}", start + 1, end + 1)]
+      }
+    };
+    let cargo_path =
+      std::env::var("CARGO_PATH").unwrap_or_else(|_| "cargo".to_string());
+    let mut cmd = Command::new(cargo_path);
+    cmd
+      .args(["aquascope", "permissions", "--format", "ndjson", "--file"])
+      .arg(&doc.path)
+      .args(selector_args);
+    if self.show_flows {
+      cmd.arg("--show-flows");
+    }
+    if let Some(dir) = doc.path.parent() {
+      cmd.current_dir(dir);
+    }
+
+    let uri = uri.to_string();
+    let version = doc.version;
+    let events = self.events.clone();
+    thread::spawn(move || {
+      let bodies = run_analysis(cmd);
+      // The server may have exited in the meantime.
+      let _ = events.send(Event::Analyzed(Analyzed {
+        uri,
+        version,
+        selector,
+        bodies,
+      }));
+    });
+  }
+
+  /// Add the result of a background analysis to the cache, answering
+  /// the requests which waited for it.
+  fn analyzed(&mut self, analyzed: Analyzed) -> Vec<Value> {
+    let Analyzed {
+      uri,
+      version,
+      selector,
+      bodies,
+    } = analyzed;
+    let Some(doc) = self.documents.get_mut(&uri) else {
+      return Vec::new();
+    };
+    if doc.version != version {
+      return Vec::new();
+    }
+    doc.running.remove(&selector);
+
+    let mut outgoing = Vec::new();
+    match bodies {
+      Ok(bodies) => {
+        for body in bodies {
+          doc.bodies.retain(|b| b.meta.def_path != body.meta.def_path);
+          doc.bodies.push(body);
+        }
+        match selector {
+          Selector::Position(line, column) => {
+            let pos = CharPos { line, column };
+            if !doc.bodies.iter().any(|b| contains(&b.meta.body_range, pos)) {
+              doc.missed_lines.insert(line);
+            }
+          }
+          Selector::Lines(start, end) => doc.analyzed_lines.push((start, end)),
+        }
+        outgoing.push(publish_diagnostics(&uri, doc));
+      }
+      Err(e) => {
+        outgoing.push(log_message(&e));
+        outgoing.push(clear_diagnostics(&uri));
+        doc.error = Some(format!("{e:#}"));
+      }
+    }
+    outgoing.extend(self.answer_pending(&uri));
+    outgoing
+  }
+
+  /// Answer the requests about `uri` which no longer need to wait,
+  /// analyzing the document for the others.
+  fn answer_pending(&mut self, uri: &str) -> Vec<Value> {
+    let Some(doc) = self.documents.get_mut(uri) else {
+      return Vec::new();
+    };
+    let mut outgoing = Vec::new();
+    let mut selectors = Vec::new();
+    for pending in mem::take(&mut doc.pending) {
+      match doc.resolve(&pending.request) {
+        Ok(result) => outgoing.push(response(&pending.id, result)),
+        Err(selector) => {
+          selectors.push(selector);
+          doc.pending.push(pending);
+        }
+      }
+    }
+    for selector in selectors {
+      self.analyze(uri, selector);
+    }
+    outgoing
+  }
+
+  fn document(&self, uri: &str) -> Result<&Document> {
+    self
+      .documents
+      .get(uri)
+      .ok_or_else(|| anyhow!("Unknown document: {uri}"))
+  }
+}
+
+/// Run `cargo aquascope`, returning the bodies it analyzed.
+fn run_analysis(mut cmd: Command) -> Result<Vec<BodyAnalysis>> {
+  log::debug!("Running {cmd:?}");
+  let output = cmd.output().context("Could not run cargo aquascope")?;
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  let bodies = stdout
+    .lines()
+    .filter_map(|line| serde_json::from_str::<BodyAnalysis>(line).ok())
+    .collect::<Vec<_>>();
+  if bodies.is_empty() && !output.status.success() {
+    bail!(
+      "cargo aquascope failed: {}",
+      String::from_utf8_lossy(&output.stderr)
+    );
+  }
+  Ok(bodies)
+}
+
+fn publish_diagnostics(uri: &str, doc: &Document) -> Value {
+  let diagnostics = doc
+    .boundaries()
+    .filter(|b| b.is_violation())
+    .map(|b| {
+      let text = line_text(&doc.text, b.location.line);
+      json!({
+        "range": word_range(text, b.location),
+        "severity": 2,
+        "source": "aquascope",
+        "message": format!(
+          "missing {} permission(s): expected {}, found {}",
+          letters(missing(b)),
+          letters(b.expected),
+          letters(b.actual)
+        ),
+      })
+    })
+    .collect::<Vec<_>>();
+  notification(
+    "textDocument/publishDiagnostics",
+    json!({"uri": uri, "diagnostics": diagnostics}),
+  )
+}
+
+fn clear_diagnostics(uri: &str) -> Value {
+  notification(
+    "textDocument/publishDiagnostics",
+    json!({"uri": uri, "diagnostics": []}),
+  )
+}
+
+fn hover_markdown(boundary: &PermissionsBoundary) -> String {
+  let mut md = format!(
+    "**Permissions**: expected `{}`, found `{}`\n\n",
+    letters(boundary.expected),
+    letters(boundary.actual)
+  );
+  if let Value::Object(data) = json!(boundary.data) {
+    for (key, value) in data {
+      md.push_str(&format!("- `{key}`: {value}\n"));
+    }
+  }
+  md
+}
+
+fn notification(method: &str, params: Value) -> Value {
+  json!({"jsonrpc": "2.0", "method": method, "params": params})
+}
+
+fn log_message(error: &anyhow::Error) -> Value {
+  notification(
+    "window/logMessage",
+    json!({
+      "type": 1,
+      "message": format!("aquascope: {error:#}"),
+    }),
+  )
+}
+
+fn response(id: &Value, result: Value) -> Value {
+  json!({"jsonrpc": "2.0", "id": id, "result": result})
+}
+
+fn error_response(id: &Value, code: i32, message: &str) -> Value {
+  json!({
+    "jsonrpc": "2.0",
+    "id": id,
+    "error": {"code": code, "message": message},
+  })
+}
+
+// --------------------
+// Positions and ranges
+
+fn lsp_position(position: &Value) -> Result<(usize, usize)> {
+  let get = |key: &str| {
+    position[key]
+      .as_u64()
+      .map(|n| n as usize)
+      .ok_or_else(|| anyhow!("Invalid position: {position}"))
+  };
+  Ok((get("line")?, get("character")?))
+}
+
+fn lsp_pos(text: &str, pos: CharPos) -> Value {
+  let line = line_text(text, pos.line);
+  json!({"line": pos.line, "character": column_to_utf16(line, pos.column)})
+}
+
+/// The range of the path starting at `pos`, up to the first character
+/// which cannot be part of an identifier.
+fn word_range(line: &str, pos: CharPos) -> Value {
+  let len = line
+    .chars()
+    .skip(pos.column)
+    .take_while(|c| c.is_alphanumeric() || *c == '_')
+    .count()
+    .max(1);
+  let start = column_to_utf16(line, pos.column);
+  let end = column_to_utf16(line, pos.column + len);
+  json!({
+    "start": {"line": pos.line, "character": start},
+    "end": {"line": pos.line, "character": end},
+  })
+}
+
+fn line_text(text: &str, line: usize) -> &str {
+  text.lines().nth(line).unwrap_or_default()
+}
+
+/// Convert a column in UTF-16 code units, as used by LSP, into characters.
+fn utf16_to_column(line: &str, character: usize) -> usize {
+  let mut units = 0;
+  line
+    .chars()
+    .take_while(|c| {
+      units += c.len_utf16();
+      units <= character
+    })
+    .count()
+}
+
+/// Convert a column in characters into UTF-16 code units.
+fn column_to_utf16(line: &str, column: usize) -> usize {
+  line.chars().take(column).map(char::len_utf16).sum()
+}
+
+/// The byte offset of `pos` within `text`.
+fn byte_offset(text: &str, pos: CharPos) -> usize {
+  let line_start = text
+    .split_inclusive('\n')
+    .take(pos.line)
+    .map(str::len)
+    .sum::<usize>();
+  let column = line_text(text, pos.line)
+    .char_indices()
+    .nth(pos.column)
+    .map_or(0, |(offset, _)| offset);
+  line_start + column
+}
+
+fn uri_to_path(uri: &str) -> Result<PathBuf> {
+  let Some(path) = uri.strip_prefix("file://") else {
+    bail!("Only file URIs are supported: {uri}");
+  };
+
+  // Decode percent-escaped bytes, e.g. `%20` for spaces.
+  let bytes = path.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    let escaped = (bytes[i] == b'%')
+      .then(|| path.get(i + 1 .. i + 3))
+      .flatten()
+      .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    match escaped {
+      Some(byte) => {
+        decoded.push(byte);
+        i += 3;
+      }
+      None => {
+        decoded.push(bytes[i]);
+        i += 1;
+      }
+    }
+  }
+
+  let path = String::from_utf8(decoded)?;
+  Ok(Path::new(&path).to_path_buf())
+}
+
+// ---------------------
+// Message transport
+
+/// Read one message, returns `None` once the client closed the stream.
+fn read_message(input: &mut impl BufRead) -> Result<Option<Value>> {
+  let mut content_length = None;
+  loop {
+    let mut header = String::new();
+    if input.read_line(&mut header)? == 0 {
+      return Ok(None);
+    }
+    let header = header.trim_end();
+    if header.is_empty() {
+      break;
+    }
+    if let Some(length) = header.strip_prefix("Content-Length:") {
+      content_length = Some(length.trim().parse::<usize>()?);
+    }
+  }
+
+  let Some(length) = content_length else {
+    bail!("Message without Content-Length header");
+  };
+  let mut content = vec![0; length];
+  input.read_exact(&mut content)?;
+  Ok(Some(serde_json::from_slice(&content)?))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> Result<()> {
+  let content = serde_json::to_string(message)?;
+  write!(output, "Content-Length: {}\r\n\r\n{content}", content.len())?;
+  output.flush()?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_read_message() {
+    let content = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+    let input = format!("Content-Length: {}\r\n\r\n{content}", content.len());
+    let mut input = input.as_bytes();
+    let message = read_message(&mut input).unwrap().unwrap();
+    assert_eq!(message["method"], "exit");
+    assert!(read_message(&mut input).unwrap().is_none());
+  }
+
+  #[test]
+  fn test_positions() {
+    let text = "fn main() {\n  let s = \"😀\"; s\n}";
+    let line = line_text(text, 1);
+    assert_eq!(column_to_utf16(line, 13), 14);
+    assert_eq!(utf16_to_column(line, 14), 13);
+    let pos = CharPos {
+      line: 1,
+      column: 13,
+    };
+    assert_eq!(&text[byte_offset(text, pos) ..], "; s\n}");
+  }
+
+  #[test]
+  fn test_uri_to_path() {
+    assert_eq!(
+      uri_to_path("file:///home/my%20crate/src/main.rs").unwrap(),
+      PathBuf::from("/home/my crate/src/main.rs")
+    );
+    assert!(uri_to_path("untitled:Untitled-1").is_err());
+  }
+
+  #[test]
+  fn test_cached_miss() {
+    let (events, _received) = mpsc::channel();
+    let mut server = Server::new(events);
+    let uri = "file:///src/main.rs";
+    let mut doc =
+      Document::new(PathBuf::from("/src/main.rs"), "fn main() {}\n\n".into());
+    doc.running.insert(Selector::Position(1, 0));
+    doc.pending.push(Pending {
+      id: json!(1),
+      request: Request::Hover(CharPos { line: 1, column: 0 }),
+    });
+    server.documents.insert(uri.to_string(), doc);
+
+    let outgoing = server.analyzed(Analyzed {
+      uri: uri.to_string(),
+      version: 0,
+      selector: Selector::Position(1, 0),
+      bodies: Ok(Vec::new()),
+    });
+    assert!(outgoing.contains(&response(&json!(1), Value::Null)));
+
+    // Hovering the line again is answered without analyzing it.
+    let hover = json!({
+      "jsonrpc": "2.0",
+      "id": 2,
+      "method": "textDocument/hover",
+      "params": {
+        "textDocument": {"uri": uri},
+        "position": {"line": 1, "character": 0},
+      },
+    });
+    let outgoing = server.handle(&hover);
+    assert_eq!(outgoing, vec![response(&json!(2), Value::Null)]);
+    assert!(server.documents[uri].running.is_empty());
+  }
+
+  #[test]
+  fn test_clear_diagnostics() {
+    let (events, _received) = mpsc::channel();
+    let mut server = Server::new(events);
+    let uri = "file:///src/main.rs";
+    let doc =
+      Document::new(PathBuf::from("/src/main.rs"), "fn main() {}".into());
+    server.documents.insert(uri.to_string(), doc);
+
+    // Saving discards the diagnostics of the previous version.
+    let save = json!({
+      "jsonrpc": "2.0",
+      "method": "textDocument/didSave",
+      "params": {
+        "textDocument": {"uri": uri},
+        "text": "fn main() {}\n",
+      },
+    });
+    let outgoing = server.handle(&save);
+    assert_eq!(outgoing, vec![clear_diagnostics(uri)]);
+
+    // As does an analysis which failed.
+    server
+      .documents
+      .get_mut(uri)
+      .unwrap()
+      .running
+      .insert(Selector::Lines(0, 0));
+    let outgoing = server.analyzed(Analyzed {
+      uri: uri.to_string(),
+      version: 1,
+      selector: Selector::Lines(0, 0),
+      bodies: Err(anyhow!("cargo aquascope failed")),
+    });
+    assert!(outgoing.contains(&clear_diagnostics(uri)));
+  }
+}
@@ -1,6 +1,7 @@
 use std::{
   borrow::Cow,
-  env,
+  env, fs,
+  path::{Path, PathBuf},
   process::{exit, Command},
   time::Instant,
 };
@@ -8,8 +9,13 @@ use std::{
 use aquascope::{
   analysis::{
     self,
-    permissions::ENABLE_FLOW_PERMISSIONS,
-    stepper::{PermIncludeMode, INCLUDE_MODE},
+    boundaries::{ENABLE_IMPLICIT_DROPS, ENABLE_MACRO_TRACING},
+    facts::FactsDump,
+    metadata::BodyMetadata,
+    permissions::{ENABLE_FLOW_PERMISSIONS, ENABLE_INTERIOR_MUTABILITY},
+    query::PermissionsQuery,
+    region_graph::RegionGraph,
+    stepper::{PermIncludeMode, ENABLE_TIMELINE, INCLUDE_MODE},
     AquascopeError, AquascopeResult,
   },
   errors::{
@@ -22,9 +28,20 @@ use rustc_hir::BodyId;
 use rustc_interface::interface::Result as RustcResult;
 use rustc_middle::ty::TyCtxt;
 use rustc_plugin::{CrateFilter, RustcPlugin, RustcPluginArgs, Utf8Path};
-use rustc_utils::{mir::borrowck_facts, source_map::find_bodies::find_bodies};
+use rustc_utils::{
+  mir::borrowck_facts,
+  source_map::{find_bodies::find_bodies, range::CharPos},
+};
 use serde::{self, Deserialize, Serialize};
 
+use crate::{
+  render::{
+    html::{self, RenderHtml},
+    text::{self, RenderText},
+  },
+  selector::BodySelector,
+};
+
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[derive(Debug, Parser, Serialize, Deserialize)]
@@ -45,14 +62,161 @@ enum AquascopeCommand {
 
     #[clap(long)]
     show_flows: bool,
+
+    /// Include the complete permissions of every place at each step.
+    #[clap(long)]
+    timeline: bool,
+
+    /// Give types containing an `UnsafeCell`, e.g. `Cell` or `Mutex`,
+    /// permission to be written through shared references.
+    #[clap(long)]
+    interior_mutability: bool,
+
+    /// Check the permissions of values implicitly dropped at the end
+    /// of their scope.
+    #[clap(long)]
+    implicit_drops: bool,
+
+    /// Trace path uses through the expansions of `macro_rules!` macros
+    /// defined in the crate, back to the tokens passed to the macro.
+    #[clap(long)]
+    trace_macros: bool,
+
+    /// Either `json`, a single array printed once every body is
+    /// analyzed, `ndjson`, one object per line printed as soon as
+    /// each body is analyzed, `text`, the annotated source of each
+    /// body for viewing in a terminal, or `html`, a standalone page
+    /// drawing the permissions of each body.
+    #[clap(long)]
+    format: Option<OutputFormat>,
+
+    /// Time budget in seconds, bodies not analyzed before it runs out
+    /// are reported as timed out.
+    #[clap(long)]
+    timeout: Option<u64>,
+
+    /// Only analyze this member of the workspace.
+    #[clap(long)]
+    package: Option<String>,
+
+    #[clap(flatten)]
+    selector: BodySelector,
+  },
+
+  /// Print the permissions of a path right before the expression at
+  /// `--position` of `--file` is evaluated.
+  Query {
+    /// The path to query, e.g. `v`, `v.0` or `*x`.
+    #[clap(long)]
+    path: String,
+
+    #[clap(long)]
+    show_flows: bool,
+
+    /// See `permissions`.
+    #[clap(long)]
+    interior_mutability: bool,
+
+    /// Either `json`, `ndjson`, `text` or `html`, see `permissions`.
+    #[clap(long)]
+    format: Option<OutputFormat>,
+
+    #[clap(flatten)]
+    selector: BodySelector,
+  },
+
+  /// Dump the input facts of rustc and the relations derived by
+  /// Aquascope for each body.
+  Facts {
+    /// Either `json` (the default), or `csv`, Soufflé input facts
+    /// written to one directory per body in `--out-dir`.
+    #[clap(long)]
+    format: Option<FactsFormat>,
+
+    /// Where to write `csv` facts, `aquascope-facts` by default.
+    #[clap(long)]
+    out_dir: Option<PathBuf>,
+
+    #[clap(flatten)]
+    selector: BodySelector,
+  },
+
+  /// Print the flows between all regions of each body.
+  RegionGraph {
+    /// Either `json` (the default), or `dot`, one Graphviz digraph per body.
+    #[clap(long)]
+    format: Option<GraphFormat>,
+
+    #[clap(flatten)]
+    selector: BodySelector,
   },
 
-  Interpreter,
+  Interpreter {
+    /// Either `json` (the default), or `html`, a standalone page
+    /// drawing the stack and heap after every step.
+    #[clap(long)]
+    format: Option<OutputFormat>,
+  },
 
   Preload,
   RustcVersion,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum OutputFormat {
+  Json,
+  Ndjson,
+  Text,
+  Html,
+}
+
+impl std::str::FromStr for OutputFormat {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "json" => Ok(Self::Json),
+      "ndjson" => Ok(Self::Ndjson),
+      "text" => Ok(Self::Text),
+      "html" => Ok(Self::Html),
+      _ => Err(format!("Could not parse: {s}")),
+    }
+  }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum FactsFormat {
+  Json,
+  Csv,
+}
+
+impl std::str::FromStr for FactsFormat {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "json" => Ok(Self::Json),
+      "csv" => Ok(Self::Csv),
+      _ => Err(format!("Could not parse: {s}")),
+    }
+  }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum GraphFormat {
+  Json,
+  Dot,
+}
+
+impl std::str::FromStr for GraphFormat {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "json" => Ok(Self::Json),
+      "dot" => Ok(Self::Dot),
+      _ => Err(format!("Could not parse: {s}")),
+    }
+  }
+}
+
 pub struct AquascopePlugin;
 impl RustcPlugin for AquascopePlugin {
   type Args = AquascopePluginArgs;
@@ -98,10 +262,37 @@ impl RustcPlugin for AquascopePlugin {
       _ => {}
     };
 
-    RustcPluginArgs {
-      filter: CrateFilter::OnlyWorkspace,
-      args,
-    }
+    // When analyzing a specific file, only the crate containing
+    // it needs to be checked.
+    let filter = match &args.command {
+      Permissions {
+        selector: BodySelector {
+          file: Some(file), ..
+        },
+        ..
+      }
+      | Query {
+        selector: BodySelector {
+          file: Some(file), ..
+        },
+        ..
+      }
+      | Facts {
+        selector: BodySelector {
+          file: Some(file), ..
+        },
+        ..
+      }
+      | RegionGraph {
+        selector: BodySelector {
+          file: Some(file), ..
+        },
+        ..
+      } => CrateFilter::CrateContainingFile(file.clone()),
+      _ => CrateFilter::OnlyWorkspace,
+    };
+
+    RustcPluginArgs { filter, args }
   }
 
   fn run(
@@ -114,39 +305,213 @@ impl RustcPlugin for AquascopePlugin {
       Permissions {
         steps_include_mode,
         show_flows,
-        ..
+        timeline,
+        interior_mutability,
+        implicit_drops,
+        trace_macros,
+        format,
+        timeout,
+        package,
+        selector,
       } => {
-        let steps_include_mode =
-          steps_include_mode.unwrap_or(PermIncludeMode::Changes);
-        let mut callbacks = AquascopeCallbacks {
+        let targets_package = is_targeted_package(package.as_deref());
+
+        // Every other workspace member is still compiled normally,
+        // crates depending on them may otherwise fail to build.
+        if let Some(package) = package {
+          if !is_package(&package) {
+            let _ = rustc_driver::catch_fatal_errors(|| {
+              rustc_driver::RunCompiler::new(
+                &compiler_args,
+                &mut DefaultCallbacks,
+              )
+              .run();
+            });
+            return Ok(());
+          }
+        }
+
+        let callbacks = AquascopeCallbacks {
           analysis: Some(permissions_analyze_body),
           output: Vec::default(),
-          should_fail: plugin_args.should_fail,
-          steps_include_mode,
+          steps_include_mode: steps_include_mode
+            .unwrap_or(PermIncludeMode::Changes),
           show_flows,
+          timeline,
+          interior_mutability,
+          implicit_drops,
+          trace_macros,
+          format: format.unwrap_or(OutputFormat::Json),
+          timeout,
+          selector,
+          targets_package,
+          selector_error: None,
+          render: Some(render_body),
+          rendered: Vec::default(),
+          rustc_start: Instant::now(),
+        };
+        run_analysis(&compiler_args, callbacks)
+      }
+      Query {
+        path,
+        show_flows,
+        interior_mutability,
+        format,
+        selector,
+      } => {
+        let Some(position) = selector.position else {
+          eprintln!("aquascope: a query requires a --file and --position");
+          exit(1);
+        };
+        let callbacks = AquascopeCallbacks {
+          analysis: Some(PathQuery {
+            position: position.into(),
+            path,
+          }),
+          output: Vec::default(),
+          steps_include_mode: PermIncludeMode::Changes,
+          show_flows,
+          timeline: false,
+          interior_mutability,
+          implicit_drops: false,
+          trace_macros: false,
+          format: format.unwrap_or(OutputFormat::Json),
+          timeout: None,
+          selector,
+          targets_package: is_targeted_package(None),
+          selector_error: None,
+          render: Some(render_body),
+          rendered: Vec::default(),
+          rustc_start: Instant::now(),
+        };
+        run_analysis(&compiler_args, callbacks)
+      }
+      Facts {
+        format,
+        out_dir,
+        selector,
+      } => {
+        let mut callbacks = AquascopeCallbacks {
+          analysis: Some(facts_analyze_body),
+          output: Vec::default(),
+          steps_include_mode: PermIncludeMode::Changes,
+          show_flows: false,
+          timeline: false,
+          interior_mutability: false,
+          implicit_drops: false,
+          trace_macros: false,
+          format: OutputFormat::Json,
+          timeout: None,
+          selector,
+          targets_package: is_targeted_package(None),
+          selector_error: None,
+          render: None,
+          rendered: Vec::default(),
+          rustc_start: Instant::now(),
+        };
+        let _ = run_with_callbacks(&compiler_args, &mut callbacks);
+        match format.unwrap_or(FactsFormat::Json) {
+          FactsFormat::Json => postprocess_output(callbacks),
+          FactsFormat::Csv => {
+            let out_dir =
+              out_dir.unwrap_or_else(|| PathBuf::from("aquascope-facts"));
+            if let Err(e) = write_souffle_facts(&out_dir, &callbacks.output) {
+              eprintln!("aquascope: could not write facts: {e}");
+              exit(1);
+            }
+            Ok(())
+          }
+        }
+      }
+      RegionGraph { format, selector } => {
+        let mut callbacks = AquascopeCallbacks {
+          analysis: Some(region_graph_analyze_body),
+          output: Vec::default(),
+          steps_include_mode: PermIncludeMode::Changes,
+          show_flows: true,
+          timeline: false,
+          interior_mutability: false,
+          implicit_drops: false,
+          trace_macros: false,
+          format: OutputFormat::Json,
+          timeout: None,
+          selector,
+          targets_package: is_targeted_package(None),
+          selector_error: None,
+          render: None,
+          rendered: Vec::default(),
           rustc_start: Instant::now(),
         };
-        log::info!("Starting rustc analysis...");
         let _ = run_with_callbacks(&compiler_args, &mut callbacks);
-        postprocess(callbacks.output)
+        match format.unwrap_or(GraphFormat::Json) {
+          GraphFormat::Json => postprocess_output(callbacks),
+          GraphFormat::Dot => {
+            for body in &callbacks.output {
+              let name =
+                format!("{}::{}", body.meta.crate_name, body.meta.def_path);
+              match &body.result {
+                Ok(graph) => print!("{}", graph.to_dot(&name)),
+                Err(e) => eprintln!("aquascope: {name}: {e}"),
+              }
+            }
+            Ok(())
+          }
+        }
       }
-      Interpreter { .. } => {
+      Interpreter { format } => {
         let mut callbacks = aquascope::interpreter::InterpretCallbacks::new(
           plugin_args.should_fail,
         );
         let _ = run_with_callbacks(&compiler_args, &mut callbacks);
-        postprocess(
+        let result =
           callbacks
             .result
             .unwrap()
-            .map_err(|_| AquascopeError::BuildError { range: None }),
-        )
+            .map_err(|e| AquascopeError::BuildError {
+              range: None,
+              msg: Some(e.to_string()),
+              code: None,
+            });
+        match (format, result) {
+          (Some(OutputFormat::Html), Ok(trace)) => {
+            let section =
+              html::render_trace(&trace, callbacks.source.as_deref());
+            print!("{}", html::document("Aquascope", &[section]));
+            Ok(())
+          }
+          (_, result) => postprocess(result),
+        }
       }
       _ => unreachable!(),
     }
   }
 }
 
+struct DefaultCallbacks;
+impl rustc_driver::Callbacks for DefaultCallbacks {}
+
+/// Is the crate being compiled a target of package `package`? Cargo sets
+/// `CARGO_PKG_NAME` for each rustc invocation, which unlike the crate name
+/// is not affected by a custom `[lib] name` or by binary targets.
+fn is_package(package: &str) -> bool {
+  env::var("CARGO_PKG_NAME").is_ok_and(|name| name == package)
+}
+
+/// Is the crate being compiled a target of the package a selector is
+/// resolved in? That is `package` if given, otherwise the primary package
+/// cargo was run on, e.g. the package in the current directory, for which
+/// cargo sets `CARGO_PRIMARY_PACKAGE`. A crate compiled outside of cargo
+/// is always targeted.
+fn is_targeted_package(package: Option<&str>) -> bool {
+  match package {
+    Some(package) => is_package(package),
+    None => {
+      env::var_os("CARGO_PKG_NAME").is_none()
+        || env::var_os("CARGO_PRIMARY_PACKAGE").is_some()
+    }
+  }
+}
+
 fn permissions_analyze_body(
   tcx: TyCtxt,
   id: BodyId,
@@ -154,11 +519,125 @@ fn permissions_analyze_body(
   analysis::AquascopeAnalysis::run(tcx, id)
 }
 
+fn facts_analyze_body(tcx: TyCtxt, id: BodyId) -> AquascopeResult<FactsDump> {
+  analysis::AquascopeAnalysis::dump_facts(tcx, id)
+}
+
+fn region_graph_analyze_body(
+  tcx: TyCtxt,
+  id: BodyId,
+) -> AquascopeResult<RegionGraph> {
+  analysis::AquascopeAnalysis::region_graph(tcx, id)
+}
+
+/// Write the facts of each body to its own directory in `out_dir`, with
+/// a `<relation>.facts` file per relation and their declarations in
+/// `schema.dl`.
+fn write_souffle_facts(
+  out_dir: &Path,
+  bodies: &[BodyAnalysis<FactsDump>],
+) -> std::io::Result<()> {
+  for body in bodies {
+    let name = format!("{}::{}", body.meta.crate_name, body.meta.def_path);
+    let dump = match &body.result {
+      Ok(dump) => dump,
+      Err(e) => {
+        eprintln!("aquascope: {name}: {e}");
+        continue;
+      }
+    };
+
+    let dir_name = name
+      .chars()
+      .map(|c| if c.is_alphanumeric() { c } else { '_' })
+      .collect::<String>();
+    let dir = out_dir.join(dir_name);
+    fs::create_dir_all(&dir)?;
+
+    let mut schema = String::new();
+    for relation in dump.input.iter().chain(&dump.aquascope) {
+      schema.push_str(&relation.to_souffle_decl());
+      let path = dir.join(format!("{}.facts", relation.name));
+      fs::write(path, relation.to_souffle())?;
+    }
+    fs::write(dir.join("schema.dl"), schema)?;
+  }
+  Ok(())
+}
+
+/// Query the permissions of a path, see [`analysis::query`].
+struct PathQuery {
+  position: CharPos,
+  path: String,
+}
+
+impl AquascopeAnalysis for PathQuery {
+  type Output = PermissionsQuery;
+  fn analyze(
+    &mut self,
+    tcx: TyCtxt,
+    id: BodyId,
+  ) -> AquascopeResult<Self::Output> {
+    analysis::AquascopeAnalysis::query(tcx, id, self.position, &self.path)
+  }
+}
+
+/// Run the analysis of `callbacks` over the crate, printing its results.
+fn run_analysis<A: AquascopeAnalysis>(
+  compiler_args: &[String],
+  mut callbacks: AquascopeCallbacks<A>,
+) -> RustcResult<()> {
+  log::info!("Starting rustc analysis...");
+  let _ = run_with_callbacks(compiler_args, &mut callbacks);
+  match callbacks.format {
+    OutputFormat::Json => postprocess_output(callbacks),
+    OutputFormat::Html => {
+      print!("{}", html::document("Aquascope", &callbacks.rendered));
+      Ok(())
+    }
+    // Each result was already printed during the analysis.
+    OutputFormat::Ndjson | OutputFormat::Text => Ok(()),
+  }
+}
+
+/// Render the analysis of a body as HTML in the `html` format, and as
+/// annotated source otherwise.
+fn render_body<T: RenderText + RenderHtml>(
+  source: &str,
+  body: &BodyAnalysis<T>,
+  format: OutputFormat,
+) -> String {
+  match format {
+    OutputFormat::Html => html::render_body(source, &body.meta, &body.result),
+    _ => {
+      let color = env::var_os("NO_COLOR").is_none();
+      text::render_body(source, &body.meta, &body.result, color)
+    }
+  }
+}
+
+/// Print the results of every body as JSON, or the error of a selector
+/// which matched no bodies, such that it is not mistaken for an empty
+/// crate.
+fn postprocess_output<A: AquascopeAnalysis>(
+  callbacks: AquascopeCallbacks<A>,
+) -> RustcResult<()> {
+  match callbacks.selector_error {
+    Some(error) => postprocess(Err::<(), _>(error)),
+    None => postprocess(callbacks.output),
+  }
+}
+
 fn postprocess<T: Serialize>(result: T) -> RustcResult<()> {
-  println!("{}", serde_json::to_string(&result).unwrap());
+  emit(&result);
   Ok(())
 }
 
+/// Print `value` as a single line of JSON.
+fn emit<T: Serialize>(value: &T) {
+  println!("{}", serde_json::to_string(value).unwrap());
+}
+
 pub fn run_with_callbacks(
   args: &[String],
   callbacks: &mut (dyn rustc_driver::Callbacks + Send),
@@ -179,7 +658,11 @@ pub fn run_with_callbacks(
   rustc_driver::catch_fatal_errors(move || {
     compiler.run();
   })
-  .map_err(|_| AquascopeError::BuildError { range: None })
+  .map_err(|_| AquascopeError::BuildError {
+    range: None,
+    msg: None,
+    code: None,
+  })
 }
 
 pub trait AquascopeAnalysis: Sized + Send + Sync {
@@ -208,13 +691,41 @@ where
   }
 }
 
-#[allow(dead_code)]
+/// The result of analyzing a single body, tagged with the body it describes.
+///
+/// The result is flattened so each entry remains a plain `{"Ok": ...}` or
+/// `{"Err": ...}` object for consumers that ignore the metadata.
+#[derive(Serialize)]
+struct BodyAnalysis<T> {
+  meta: BodyMetadata,
+  #[serde(flatten)]
+  result: AquascopeResult<T>,
+}
+
+/// Renders the analysis of a body, given the source of its file, in the
+/// `text` or `html` format.
+type Renderer<T> = fn(&str, &BodyAnalysis<T>, OutputFormat) -> String;
+
 struct AquascopeCallbacks<A: AquascopeAnalysis> {
   analysis: Option<A>,
-  output: Vec<AquascopeResult<A::Output>>,
-  should_fail: bool,
+  output: Vec<BodyAnalysis<A::Output>>,
   steps_include_mode: PermIncludeMode,
   show_flows: bool,
+  timeline: bool,
+  interior_mutability: bool,
+  implicit_drops: bool,
+  trace_macros: bool,
+  format: OutputFormat,
+  timeout: Option<u64>,
+  selector: BodySelector,
+  /// Is the crate a target of the package the selector is resolved in?
+  targets_package: bool,
+  /// Set if the selector matched no bodies of the crate it targets.
+  selector_error: Option<AquascopeError>,
+  /// Set if the output of the analysis can be rendered.
+  render: Option<Renderer<A::Output>>,
+  /// Bodies rendered as HTML, printed as one document after the analysis.
+  rendered: Vec<String>,
   rustc_start: Instant,
 }
 
@@ -235,15 +746,72 @@ impl<A: AquascopeAnalysis> rustc_driver::Callbacks for AquascopeCallbacks<A> {
 
     fluid_set!(INCLUDE_MODE, self.steps_include_mode);
     fluid_set!(ENABLE_FLOW_PERMISSIONS, self.show_flows);
+    fluid_set!(ENABLE_TIMELINE, self.timeline);
+    fluid_set!(ENABLE_INTERIOR_MUTABILITY, self.interior_mutability);
+    fluid_set!(ENABLE_IMPLICIT_DROPS, self.implicit_drops);
+    fluid_set!(ENABLE_MACRO_TRACING, self.trace_macros);
+
+    let bodies = self.selector.select(tcx, find_bodies(tcx));
+
+    // Every member of a workspace is analyzed with the same selector, a
+    // miss is only reported for the crate the selector targets.
+    let targets_crate = if self.selector.file.is_some() {
+      self.selector.targets_crate(tcx)
+    } else {
+      self.targets_package
+    };
+    if bodies.is_empty() && !self.selector.is_empty() && targets_crate {
+      let msg = format!("no bodies matched the selector: {}", self.selector);
+      let error = AquascopeError::InvalidQuery { msg };
+      match self.format {
+        OutputFormat::Json => self.selector_error = Some(error),
+        OutputFormat::Ndjson => emit(&Err::<(), _>(error)),
+        OutputFormat::Text | OutputFormat::Html => {
+          eprintln!("aquascope: {error}");
+        }
+      }
+    }
 
-    let _start = Instant::now();
-
+    let source_map = tcx.sess.source_map();
     let mut analysis = self.analysis.take().unwrap();
-    find_bodies(tcx).into_iter().for_each(|(_, body_id)| {
+    bodies.into_iter().for_each(|(_, body_id)| {
       // Track diagnostics for the analysis of the current body
       let def_id = tcx.hir().body_owner_def_id(body_id);
       track_body_diagnostics(def_id);
-      self.output.push(analysis.analyze(tcx, body_id));
+      let meta = match BodyMetadata::new(tcx, body_id) {
+        Ok(meta) => meta,
+        Err(error) => {
+          eprintln!("aquascope: {error}");
+          return;
+        }
+      };
+      let result = match self.timeout {
+        Some(limit_secs)
+          if self.rustc_start.elapsed().as_secs() >= limit_secs =>
+        {
+          Err(AquascopeError::Timeout { limit_secs })
+        }
+        _ => analysis.analyze(tcx, body_id),
+      };
+      let result = BodyAnalysis { meta, result };
+      let source_file =
+        source_map.lookup_source_file(tcx.hir().body(body_id).value.span.lo());
+      let source = source_file.src.as_ref().map_or("", |src| src.as_str());
+      match self.format {
+        OutputFormat::Json => self.output.push(result),
+        OutputFormat::Ndjson => emit(&result),
+        OutputFormat::Text | OutputFormat::Html => {
+          let render = self
+            .render
+            .expect("the output of the analysis can't be rendered");
+          let rendered = render(source, &result, self.format);
+          if self.format == OutputFormat::Html {
+            self.rendered.push(rendered);
+          } else {
+            print!("{rendered}");
+          }
+        }
+      }
     });
 
     log::debug!("Callback analysis took {:?}", self.rustc_start.elapsed());
@@ -0,0 +1,731 @@
+//! Render analyses as self-contained, static HTML documents.
+//!
+//! The documents need neither the JavaScript frontend nor any external
+//! resources, so they can be embedded in documentation, slides or issue
+//! trackers. Permissions are drawn like the editor draws them: the
+//! expected permissions of each boundary inline and the step tables
+//! beside each line. Hovering a missing permission highlights the loan
+//! or move which removed it, which only needs CSS.
+
+use std::fmt::Write;
+
+use aquascope::{
+  analysis::{
+    boundaries::{Desugaring, ImplicitDrop, PermissionsBoundary},
+    metadata::BodyMetadata,
+    permissions::{PermissionsData, Refiner},
+    query::PermissionsQuery,
+    stepper::{PermissionsDataDiff, PermissionsLineDisplay, ValueStep},
+    AnalysisOutput, AquascopeResult,
+  },
+  interpreter::{
+    Abbreviated, MFrame, MLocal, MMemorySegment, MPath, MPathSegment, MResult,
+    MStep, MTrace, MUndefinedBehavior, MValue,
+  },
+};
+use rustc_utils::source_map::range::CharRange;
+
+const STYLE: &str = r#"
+body { font-family: sans-serif; margin: 2em; }
+h2 { font-size: 1em; }
+pre, .code, .mstep { font-family: ui-monospace, monospace; }
+table.aquascope-body { border-collapse: collapse; margin-bottom: 2em; }
+table.aquascope-body > tbody > tr > td { vertical-align: top; padding: 0 0.5em; }
+td.gutter { color: #999; text-align: right; user-select: none; }
+td.code { white-space: pre; }
+.stack { font-size: 0.75em; margin: 0 1px; padding: 0 2px; border-radius: 3px; background: #eef; }
+.perm.ok { color: #2a7; }
+.perm.missing { color: #d33; text-decoration: underline; }
+.perm.unchecked { color: #888; }
+.perm.custom-drop { color: #888; }
+.perm.operator { color: #888; }
+.perm.in-macro { color: #888; }
+.point { outline: 1px solid #333; }
+table.step { font-size: 0.8em; border: 1px solid #ccc; margin-bottom: 2px; }
+table.step td { padding: 0 3px; }
+.gained { color: #2a7; }
+.lost { color: #d33; text-decoration: line-through; }
+.unset { color: #bbb; }
+.error { color: #d33; }
+.mstep { display: flex; gap: 2em; border-top: 1px solid #ddd; padding: 0.5em 0; }
+.mstep-header { min-width: 16em; white-space: pre; }
+.mframe, .mheap { border: 1px solid #ccc; margin-bottom: 4px; }
+.mframe th, .mheap th { text-align: left; background: #f4f4f4; }
+.mframe td, .mheap td { padding: 0 4px; }
+.moved { color: #bbb; }
+"#;
+
+/// Analysis results which can be rendered as HTML alongside the source of their body.
+pub trait RenderHtml {
+  fn render_html(&self, source: &str) -> String;
+}
+
+impl RenderHtml for AnalysisOutput {
+  fn render_html(&self, source: &str) -> String {
+    render_output(source, self)
+  }
+}
+
+impl RenderHtml for PermissionsQuery {
+  fn render_html(&self, _source: &str) -> String {
+    let perms = self.permissions;
+    let letters = [("R", perms.read), ("W", perms.write), ("O", perms.drop)]
+      .into_iter()
+      .map(|(letter, has)| {
+        if has {
+          format!("<span class=\"perm ok\">{letter}</span>")
+        } else {
+          "<span class=\"unset\">‒</span>".to_string()
+        }
+      })
+      .collect::<String>();
+
+    let refiners = self
+      .refiners
+      .iter()
+      .map(|refiner| {
+        let cause = match refiner.refiner {
+          Refiner::Loan(_) => "borrowed",
+          Refiner::Move(_) => "moved",
+        };
+        let explanation = refiner
+          .explanation
+          .as_ref()
+          .map(|explanation| {
+            let mut items = explanation
+              .flows
+              .iter()
+              .map(|flow| {
+                format!(
+                  "<li>flows from <code>{}</code> into <code>{}</code> at {}</li>",
+                  escape(flow.from.as_deref().unwrap_or("_")),
+                  escape(flow.to.as_deref().unwrap_or("_")),
+                  position(&flow.range)
+                )
+              })
+              .collect::<String>();
+            if let (Some(holder), Some(later_use)) =
+              (&explanation.holder, &explanation.later_use)
+            {
+              items.push_str(&format!(
+                "<li>live while <code>{}</code> is used at {}</li>",
+                escape(&holder.name),
+                position(later_use)
+              ));
+            }
+            format!("<ul>{items}</ul>")
+          })
+          .unwrap_or_default();
+        format!(
+          "<li>{cause} at {}{explanation}</li>",
+          position(&refiner.range)
+        )
+      })
+      .collect::<String>();
+
+    format!(
+      "<p><code>{}</code> at {}: <span class=\"stack\">{letters}</span></p>\n<ul>{refiners}</ul>\n",
+      escape(&self.path),
+      position(&self.range)
+    )
+  }
+}
+
+/// A 1-based `line:column` for the start of `range`.
+fn position(range: &CharRange) -> String {
+  format!("{}:{}", range.start.line + 1, range.start.column + 1)
+}
+
+/// Wrap rendered `sections` in a complete HTML document.
+pub fn document(title: &str, sections: &[String]) -> String {
+  format!(
+    "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+     <title>{}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+    escape(title),
+    sections.concat()
+  )
+}
+
+/// Render the analysis of one body. `source` is the text of the file containing it.
+pub fn render_body<T: RenderHtml>(
+  source: &str,
+  meta: &BodyMetadata,
+  result: &AquascopeResult<T>,
+) -> String {
+  let mut out = format!(
+    "<section>\n<h2>{} <small>({}:{})</small></h2>\n",
+    escape(&meta.def_path),
+    escape(&meta.filename),
+    meta.body_range.start.line + 1
+  );
+
+  match result {
+    Ok(output) => out.push_str(&output.render_html(source)),
+    Err(e) => {
+      let _ = writeln!(
+        out,
+        "<p class=\"error\">error: {}</p>",
+        escape(&e.to_string())
+      );
+    }
+  }
+
+  out.push_str("</section>\n");
+  out
+}
+
+fn render_output(source: &str, output: &AnalysisOutput) -> String {
+  let lines = source.lines().collect::<Vec<_>>();
+  if lines.is_empty() {
+    return String::new();
+  }
+
+  let first = output.body_range.start.line;
+  let last = output
+    .body_range
+    .end
+    .line
+    .min(lines.len().saturating_sub(1));
+
+  // The CSS classes of every character of the body, used to highlight
+  // the points and regions of loans and moves. Ranges in other files,
+  // e.g. the definition of a macro, can't be shown alongside `source`.
+  let file = output.body_range.filename;
+  let mut classes = (first ..= last)
+    .map(|line| vec![Vec::new(); lines[line].chars().count()])
+    .collect::<Vec<_>>();
+  let mut mark = |range: &CharRange, class: String| {
+    if range.filename != file {
+      return;
+    }
+    for line in range.start.line ..= range.end.line.min(last) {
+      let Some(columns) = line
+        .checked_sub(first)
+        .and_then(|index| classes.get_mut(index))
+      else {
+        continue;
+      };
+      let start = if line == range.start.line {
+        range.start.column
+      } else {
+        0
+      };
+      let end = if line == range.end.line {
+        range.end.column.min(columns.len())
+      } else {
+        columns.len()
+      };
+      for column in start .. end {
+        columns[column].push(class.clone());
+      }
+    }
+  };
+
+  let mut styles = String::new();
+  for (key, range) in &output.loan_points.0 {
+    mark(range, format!("point point-loan-{}", key.0));
+  }
+  for (key, range) in &output.move_points.0 {
+    mark(range, format!("point point-move-{}", key.0));
+  }
+  let regions = output
+    .loan_regions
+    .0
+    .values()
+    .chain(output.move_regions.0.values());
+  for region in regions {
+    let name = refiner_name(&region.refiner_point);
+    for range in &region.refined_ranges {
+      mark(range, format!("region-{name}"));
+    }
+    let _ = writeln!(
+      styles,
+      ".aquascope-body:has(.refiner-{name}:hover) .region-{name} {{ background: #fdd; }}"
+    );
+  }
+
+  let mut out = format!(
+    "<style>{styles}</style>\n<table class=\"aquascope-body\"><tbody>\n"
+  );
+  for (line, columns) in (first ..= last).zip(&classes) {
+    let mut boundaries = output
+      .boundaries
+      .iter()
+      .filter(|b| b.location.line == line)
+      .collect::<Vec<_>>();
+    boundaries.sort_by_key(|b| b.location.column);
+
+    let steps = output
+      .steps
+      .iter()
+      .filter(|display| {
+        display.location.filename == file && display.location.start.line == line
+      })
+      .map(render_steps)
+      .collect::<String>();
+
+    let _ = writeln!(
+      out,
+      "<tr><td class=\"gutter\">{}</td><td class=\"code\">{}</td><td>{steps}</td></tr>",
+      line + 1,
+      render_line(lines[line], columns, &boundaries)
+    );
+  }
+  out.push_str("</tbody></table>\n");
+  out
+}
+
+/// Render a line of source, where each character is wrapped with
+/// its `classes`, inserting the permission stack of each boundary.
+fn render_line(
+  line: &str,
+  classes: &[Vec<String>],
+  boundaries: &[&PermissionsBoundary],
+) -> String {
+  let mut out = String::new();
+  let mut boundaries = boundaries.iter().peekable();
+  let mut open: Option<&[String]> = None;
+  let no_classes = Vec::new();
+  for (column, c) in line.chars().enumerate() {
+    let current = classes.get(column).unwrap_or(&no_classes).as_slice();
+    let has_stack = boundaries
+      .peek()
+      .is_some_and(|b| b.location.column <= column);
+    if has_stack || open != Some(current) {
+      if open.take().is_some_and(|open| !open.is_empty()) {
+        out.push_str("</span>");
+      }
+      while let Some(b) = boundaries.next_if(|b| b.location.column <= column) {
+        out.push_str(&render_stack(b));
+      }
+      if !current.is_empty() {
+        let _ = write!(out, "<span class=\"{}\">", current.join(" "));
+      }
+      open = Some(current);
+    }
+    out.push_str(&escape(&c.to_string()));
+  }
+  if open.is_some_and(|open| !open.is_empty()) {
+    out.push_str("</span>");
+  }
+  for b in boundaries {
+    out.push_str(&render_stack(b));
+  }
+  out
+}
+
+/// Render the expected permissions of a boundary, marking missing ones.
+fn render_stack(boundary: &PermissionsBoundary) -> String {
+  let data = &boundary.data;
+  let letters = [
+    (
+      "R",
+      boundary.expected.read,
+      boundary.actual.read,
+      data.loan_read_refined,
+    ),
+    (
+      "W",
+      boundary.expected.write,
+      boundary.actual.write,
+      data.loan_write_refined,
+    ),
+    (
+      "O",
+      boundary.expected.drop,
+      boundary.actual.drop,
+      data.loan_drop_refined,
+    ),
+    (
+      "I",
+      boundary.expected.interior_write,
+      boundary.actual.interior_write,
+      data.loan_interior_write_refined(),
+    ),
+  ];
+
+  let mut stack = letters
+    .into_iter()
+    .filter(|(_, expected, ..)| *expected)
+    .map(|(letter, _, actual, loan)| {
+      if actual {
+        return format!("<span class=\"perm ok\">{letter}</span>");
+      }
+      let refiner = loan
+        .map(Refiner::Loan)
+        .or_else(|| data.path_moved.map(Refiner::Move));
+      let class = refiner.map_or(String::new(), |refiner| {
+        format!(" refiner-{}", refiner_name(&refiner))
+      });
+      format!(
+        "<span class=\"perm missing{class}\" title=\"{}\">{letter}</span>",
+        missing_reason(letter, data)
+      )
+    })
+    .collect::<String>();
+
+  if let Some(flow) = &boundary.expecting_flow {
+    let class = if flow.is_violation { "missing" } else { "ok" };
+    let _ = write!(stack, "<span class=\"perm {class}\">F</span>");
+  }
+
+  if let Some(unchecked) = &boundary.unchecked {
+    let class = if unchecked.is_violation() {
+      "missing"
+    } else {
+      "unchecked"
+    };
+    let mut title = String::from("unchecked access");
+    for alias in &unchecked.aliases {
+      let _ = write!(
+        title,
+        ", aliases {} from {}",
+        escape(&alias.path),
+        position(&alias.created_at)
+      );
+    }
+    let _ = write!(
+      stack,
+      "<span class=\"perm {class}\" title=\"{title}\">U</span>"
+    );
+  }
+
+  if let Some(operator) = &boundary.operator {
+    let borrow = if operator.mutable {
+      "&amp;mut"
+    } else {
+      "&amp;"
+    };
+    let title =
+      format!("implicit {borrow} by {}", escape(&operator.trait_name));
+    let _ = write!(
+      stack,
+      "<span class=\"perm operator\" title=\"{title}\">&amp;</span>"
+    );
+  }
+
+  if let Some(name) = &boundary.in_macro {
+    let _ = write!(
+      stack,
+      "<span class=\"perm in-macro\" title=\"used inside {}!\">M</span>",
+      escape(name)
+    );
+  }
+
+  if let Some(ImplicitDrop::Custom) = boundary.implicit_drop {
+    stack.push_str(
+      "<span class=\"perm custom-drop\" title=\"runs a Drop impl\">D</span>",
+    );
+  }
+
+  if stack.is_empty() {
+    return String::new();
+  }
+
+  // The call moving or borrowing the operand of a desugaring is not in
+  // the source, so name it.
+  let title = match boundary.desugaring {
+    Some(Desugaring::ForLoop) => " title=\"used by IntoIterator::into_iter\"",
+    Some(Desugaring::Try) => " title=\"used by Try::branch\"",
+    Some(Desugaring::Await) => " title=\"used by IntoFuture::into_future\"",
+    None => "",
+  };
+
+  format!("<span class=\"stack\"{title}>{stack}</span>")
+}
+
+/// A short explanation for why the `letter` permission is missing.
+fn missing_reason(letter: &str, data: &PermissionsData) -> &'static str {
+  if data.path_moved.is_some() {
+    "the path was moved"
+  } else if data.path_uninitialized {
+    "the path is not initialized"
+  } else if letter == "W" && !data.type_writeable {
+    "the path is not declared as mutable"
+  } else if letter == "O" && !data.type_droppable {
+    "the path does not own its data"
+  } else if letter == "I" && !data.type_interior_writeable {
+    "the path has no interior mutability"
+  } else {
+    "the path is borrowed"
+  }
+}
+
+fn refiner_name(refiner: &Refiner) -> String {
+  match refiner {
+    Refiner::Loan(key) => format!("loan-{}", key.0),
+    Refiner::Move(key) => format!("move-{}", key.0),
+  }
+}
+
+/// Render the step tables shown at a line.
+fn render_steps(display: &PermissionsLineDisplay) -> String {
+  display
+    .state
+    .iter()
+    .map(|table| {
+      let rows = table
+        .state
+        .iter()
+        .map(|(place, diff)| render_diff(place, diff))
+        .collect::<String>();
+      format!("<table class=\"step\">{rows}</table>")
+    })
+    .collect()
+}
+
+fn render_diff(place: &str, diff: &PermissionsDataDiff) -> String {
+  let perms = &diff.permissions;
+  let cells = [("R", perms.read), ("W", perms.write), ("O", perms.drop)]
+    .into_iter()
+    .map(|(letter, step)| match step {
+      ValueStep::High { .. } => format!("<td class=\"gained\">+{letter}</td>"),
+      ValueStep::Low => format!("<td class=\"lost\">{letter}</td>"),
+      ValueStep::None { value: Some(true) } => format!("<td>{letter}</td>"),
+      ValueStep::None { .. } => "<td class=\"unset\">‒</td>".to_string(),
+    })
+    .collect::<String>();
+  format!("<tr><td>{}</td>{cells}</tr>", escape(place))
+}
+
+/// Render the steps of an interpreted program, showing the stack and heap
+/// after each step. `source` is the text of the file containing `main`.
+pub fn render_trace(trace: &MTrace<CharRange>, source: Option<&str>) -> String {
+  let lines = source.map(|source| source.lines().collect::<Vec<_>>());
+  let mut out = String::from("<section>\n");
+  for (index, step) in trace.steps.iter().enumerate() {
+    let location = step.stack.frames.last().map(|frame| &frame.location);
+    let header = location.map_or(String::new(), |location| {
+      let line = location.start.line;
+      let text = lines
+        .as_ref()
+        .and_then(|lines| lines.get(line))
+        .map_or("", |text| text.trim());
+      format!("L{}: {}", line + 1, escape(text))
+    });
+    let _ = writeln!(
+      out,
+      "<div class=\"mstep\" id=\"s{index}\"><div class=\"mstep-header\">{header}</div>\
+       <div>{}</div><div>{}</div></div>",
+      render_stack_frames(index, step),
+      render_heap(index, step)
+    );
+  }
+
+  if let MResult::Error(ub) = &trace.result {
+    let msg = match ub {
+      MUndefinedBehavior::PointerUseAfterFree { .. } => {
+        "pointer used after its pointee was freed".to_string()
+      }
+      MUndefinedBehavior::Other(msg) => msg.clone(),
+    };
+    let _ = writeln!(
+      out,
+      "<p class=\"error\">undefined behavior: {}</p>",
+      escape(&msg)
+    );
+  }
+
+  out.push_str("</section>\n");
+  out
+}
+
+fn render_stack_frames(step: usize, mstep: &MStep<CharRange>) -> String {
+  mstep
+    .stack
+    .frames
+    .iter()
+    .enumerate()
+    .map(|(index, frame)| render_frame(step, index, frame))
+    .collect()
+}
+
+fn render_frame(
+  step: usize,
+  index: usize,
+  frame: &MFrame<CharRange>,
+) -> String {
+  let locals = frame
+    .locals
+    .iter()
+    .map(|local| render_local(step, index, local))
+    .collect::<String>();
+  format!(
+    "<table class=\"mframe\"><tr><th colspan=\"2\">{}</th></tr>{locals}</table>",
+    escape(&frame.name)
+  )
+}
+
+fn render_local(step: usize, frame: usize, local: &MLocal) -> String {
+  let class = if local.moved_paths.is_empty() {
+    ""
+  } else {
+    " class=\"moved\" title=\"moved\""
+  };
+  format!(
+    "<tr id=\"s{step}-f{frame}-{name}\"{class}><td>{name}</td><td>{}</td></tr>",
+    render_value(step, &local.value),
+    name = escape(&local.name)
+  )
+}
+
+fn render_heap(step: usize, mstep: &MStep<CharRange>) -> String {
+  if mstep.heap.locations.is_empty() {
+    return String::new();
+  }
+
+  let rows = mstep
+    .heap
+    .locations
+    .iter()
+    .enumerate()
+    .map(|(index, value)| {
+      format!(
+        "<tr id=\"s{step}-h{index}\"><td>#{index}</td><td>{}</td></tr>",
+        render_value(step, value)
+      )
+    })
+    .collect::<String>();
+  format!(
+    "<table class=\"mheap\"><tr><th colspan=\"2\">Heap</th></tr>{rows}</table>"
+  )
+}
+
+/// Render a value, pointers link to the location they point to.
+fn render_value(step: usize, value: &MValue) -> String {
+  let list = |values: &[MValue]| {
+    values
+      .iter()
+      .map(|value| render_value(step, value))
+      .collect::<Vec<_>>()
+      .join(", ")
+  };
+
+  match value {
+    MValue::Bool(b) => b.to_string(),
+    MValue::Char(c) => {
+      let c = u32::try_from(*c)
+        .ok()
+        .and_then(char::from_u32)
+        .unwrap_or(char::REPLACEMENT_CHARACTER);
+      escape(&format!("{c:?}"))
+    }
+    MValue::Uint(n) => n.to_string(),
+    MValue::Int(n) => n.to_string(),
+    MValue::Float(n) => n.to_string(),
+    MValue::Tuple(values) => format!("({})", list(values)),
+    MValue::Array(Abbreviated::All(values)) => format!("[{}]", list(values)),
+    MValue::Array(Abbreviated::Only(values, last)) => {
+      format!("[{}, …, {}]", list(values), render_value(step, last))
+    }
+    MValue::Adt {
+      name,
+      variant,
+      fields,
+      ..
+    } => {
+      let name = match variant {
+        Some(variant) => format!("{name}::{variant}"),
+        None => name.clone(),
+      };
+      let fields = fields
+        .iter()
+        .map(|(field, value)| {
+          format!("{}: {}", escape(field), render_value(step, value))
+        })
+        .collect::<Vec<_>>();
+      if fields.is_empty() {
+        escape(&name)
+      } else {
+        format!("{} {{ {} }}", escape(&name), fields.join(", "))
+      }
+    }
+    MValue::Pointer { path, .. } => {
+      let target = match &path.segment {
+        MMemorySegment::Stack { frame, local } => {
+          format!("s{step}-f{frame}-{}", escape(local))
+        }
+        MMemorySegment::Heap { index } => format!("s{step}-h{index}"),
+      };
+      format!(
+        "<a href=\"#{target}\">→ {}</a>",
+        escape(&path_to_string(path))
+      )
+    }
+    MValue::Unallocated { .. } => {
+      "<span class=\"unset\" title=\"deallocated\">⊥</span>".to_string()
+    }
+  }
+}
+
+fn path_to_string(path: &MPath) -> String {
+  let mut s = match &path.segment {
+    MMemorySegment::Stack { local, .. } => local.clone(),
+    MMemorySegment::Heap { index } => format!("#{index}"),
+  };
+  for part in &path.parts {
+    match part {
+      MPathSegment::Field(index) => {
+        let _ = write!(s, ".{index}");
+      }
+      MPathSegment::Index(index) => {
+        let _ = write!(s, "[{index}]");
+      }
+      MPathSegment::Subslice(start, end) => {
+        let _ = write!(s, "[{start}..{end}]");
+      }
+    }
+  }
+  s
+}
+
+fn escape(s: &str) -> String {
+  let mut escaped = String::with_capacity(s.len());
+  for c in s.chars() {
+    match c {
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '&' => escaped.push_str("&amp;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      _ => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_escape() {
+    assert_eq!(escape("Vec<&'a str>"), "Vec&lt;&amp;&#39;a str&gt;");
+  }
+
+  #[test]
+  fn test_render_line_classes() {
+    let classes = vec![
+      vec![],
+      vec!["region-loan-0".to_string()],
+      vec!["region-loan-0".to_string()],
+      vec![],
+    ];
+    assert_eq!(
+      render_line("a<b;", &classes, &[]),
+      "a<span class=\"region-loan-0\">&lt;b</span>;"
+    );
+  }
+
+  #[test]
+  fn test_render_pointer() {
+    let value = MValue::Pointer {
+      path: MPath {
+        segment: MMemorySegment::Heap { index: 1 },
+        parts: vec![MPathSegment::Index(2)],
+      },
+      range: None,
+    };
+    assert_eq!(render_value(0, &value), "<a href=\"#s0-h1\">→ #1[2]</a>");
+  }
+}
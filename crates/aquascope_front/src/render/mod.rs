@@ -0,0 +1,13 @@
+//! Renderers for viewing analysis results without the web frontend.
+
+pub mod html;
+pub mod text;
+
+/// Print `s` with the ANSI escape `code` when `color` is enabled.
+pub(crate) fn paint(s: &str, code: &str, color: bool) -> String {
+  if color {
+    format!("\x1b[{code}m{s}\x1b[0m")
+  } else {
+    s.to_string()
+  }
+}
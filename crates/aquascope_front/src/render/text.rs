@@ -0,0 +1,349 @@
+//! Render permissions analyses as (ANSI colored) text for terminals.
+//!
+//! The source of each body is printed with the expected permissions of
+//! every boundary inline, e.g. `v[RW]`, where permissions missing at
+//! that point are highlighted. Permission steps are printed beside the
+//! line they belong to, showing gained (`+R`) and lost (`-W`) permissions.
+
+use aquascope::analysis::{
+  boundaries::{ImplicitDrop, PermissionsBoundary},
+  metadata::BodyMetadata,
+  permissions::{Permissions, Refiner},
+  query::PermissionsQuery,
+  stepper::{PermissionsDataDiff, PermissionsLineDisplay, ValueStep},
+  AnalysisOutput, AquascopeResult,
+};
+use rustc_utils::source_map::range::CharRange;
+
+use super::paint;
+
+const BOLD: &str = "1";
+const DIM: &str = "2";
+const GREEN: &str = "32";
+const RED: &str = "1;4;31";
+
+/// Analysis results which can be rendered alongside the source of their body.
+pub trait RenderText {
+  fn render_text(&self, source: &str, color: bool) -> String;
+}
+
+impl RenderText for AnalysisOutput {
+  fn render_text(&self, source: &str, color: bool) -> String {
+    render_output(source, self, color)
+  }
+}
+
+impl RenderText for PermissionsQuery {
+  fn render_text(&self, _source: &str, color: bool) -> String {
+    let perms = self.permissions;
+    let mut letters =
+      vec![("R", perms.read), ("W", perms.write), ("O", perms.drop)];
+    if self.data.type_interior_writeable {
+      letters.push(("I", perms.interior_write));
+    }
+    let letters = letters
+      .into_iter()
+      .map(|(letter, has)| {
+        if has {
+          paint(letter, GREEN, color)
+        } else {
+          paint("‒", DIM, color)
+        }
+      })
+      .collect::<Vec<_>>()
+      .join(" ");
+
+    let mut out = format!(
+      "  {} at {}: {letters}\n",
+      paint(&self.path, BOLD, color),
+      position(&self.range)
+    );
+    for refiner in &self.refiners {
+      let cause = match refiner.refiner {
+        Refiner::Loan(_) => "borrowed",
+        Refiner::Move(_) => "moved",
+      };
+      out.push_str(&format!("    {cause} at {}\n", position(&refiner.range)));
+      if let Some(explanation) = &refiner.explanation {
+        for flow in &explanation.flows {
+          let from = flow.from.as_deref().unwrap_or("_");
+          let to = flow.to.as_deref().unwrap_or("_");
+          out.push_str(&format!(
+            "      flows from {from} into {to} at {}\n",
+            position(&flow.range)
+          ));
+        }
+        if let (Some(holder), Some(later_use)) =
+          (&explanation.holder, &explanation.later_use)
+        {
+          out.push_str(&format!(
+            "      live while {} is used at {}\n",
+            paint(&holder.name, BOLD, color),
+            position(later_use)
+          ));
+        }
+      }
+    }
+    out
+  }
+}
+
+/// A 1-based `line:column` for the start of `range`.
+fn position(range: &CharRange) -> String {
+  format!("{}:{}", range.start.line + 1, range.start.column + 1)
+}
+
+/// Render the analysis of one body. `source` is the text of the file containing it.
+pub fn render_body<T: RenderText>(
+  source: &str,
+  meta: &BodyMetadata,
+  result: &AquascopeResult<T>,
+  color: bool,
+) -> String {
+  let header = format!(
+    "── {} ({}:{})",
+    meta.def_path,
+    meta.filename,
+    meta.body_range.start.line + 1
+  );
+  let mut out = paint(&header, BOLD, color);
+  out.push('\n');
+
+  match result {
+    Ok(output) => out.push_str(&output.render_text(source, color)),
+    Err(e) => {
+      out.push_str(&format!("  {}: {e}\n", paint("error", RED, color)));
+    }
+  }
+
+  out
+}
+
+fn render_output(source: &str, output: &AnalysisOutput, color: bool) -> String {
+  let lines = source.lines().collect::<Vec<_>>();
+  if lines.is_empty() {
+    return String::new();
+  }
+
+  let first = output.body_range.start.line;
+  let last = output
+    .body_range
+    .end
+    .line
+    .min(lines.len().saturating_sub(1));
+
+  let rendered = (first ..= last)
+    .map(|line| {
+      let mut boundaries = output
+        .boundaries
+        .iter()
+        .filter(|b| b.location.line == line)
+        .collect::<Vec<_>>();
+      boundaries.sort_by_key(|b| b.location.column);
+      render_line(lines[line], &boundaries, color)
+    })
+    .collect::<Vec<_>>();
+
+  let gutter_width = (last + 1).to_string().len();
+  let source_width = rendered
+    .iter()
+    .map(|line| visible_width(line))
+    .max()
+    .unwrap_or(0);
+
+  let mut out = String::new();
+  for (line, text) in (first ..= last).zip(rendered) {
+    let gutter = paint(&format!("{:>gutter_width$} │ ", line + 1), DIM, color);
+    let rows = output
+      .steps
+      .iter()
+      .filter(|display| display.location.start.line == line)
+      .flat_map(|display| render_steps(display, color))
+      .collect::<Vec<_>>();
+
+    out.push_str(&gutter);
+    out.push_str(&text);
+    for (i, row) in rows.iter().enumerate() {
+      if i > 0 {
+        out.push('\n');
+        out.push_str(&" ".repeat(gutter_width + 3));
+        out.push_str(&" ".repeat(source_width));
+      } else {
+        out.push_str(&" ".repeat(source_width - visible_width(&text)));
+      }
+      out.push_str(&paint("  │ ", DIM, color));
+      out.push_str(row);
+    }
+    out.push('\n');
+  }
+
+  out
+}
+
+/// Render a line of source, inserting the permission stack of each boundary.
+fn render_line(
+  line: &str,
+  boundaries: &[&PermissionsBoundary],
+  color: bool,
+) -> String {
+  let mut out = String::new();
+  let mut boundaries = boundaries.iter().peekable();
+  for (column, c) in line.chars().enumerate() {
+    while let Some(b) = boundaries.next_if(|b| b.location.column <= column) {
+      out.push_str(&render_stack(b, color));
+    }
+    out.push(c);
+  }
+  for b in boundaries {
+    out.push_str(&render_stack(b, color));
+  }
+  out
+}
+
+/// Render the expected permissions of a boundary, highlighting missing ones.
+fn render_stack(boundary: &PermissionsBoundary, color: bool) -> String {
+  let expected = boundary.expected;
+  let actual = boundary.actual;
+  let mut stack = permission_letters(expected)
+    .into_iter()
+    .map(|(letter, has)| {
+      let code = if has(&actual) { GREEN } else { RED };
+      paint(letter, code, color)
+    })
+    .collect::<String>();
+
+  if let Some(flow) = &boundary.expecting_flow {
+    let code = if flow.is_violation { RED } else { GREEN };
+    stack.push_str(&paint("F", code, color));
+  }
+
+  // Raw pointer accesses are unchecked, but the places they alias may
+  // lack the expected permissions.
+  if let Some(unchecked) = &boundary.unchecked {
+    let code = if unchecked.is_violation() { RED } else { DIM };
+    stack.push_str(&paint("U", code, color));
+  }
+
+  // An operand implicitly borrowed by an overloaded operator, e.g. `v`
+  // in `v[i]`.
+  if boundary.operator.is_some() {
+    stack.push_str(&paint("&", DIM, color));
+  }
+
+  // A use traced into the expansion of a user macro.
+  if boundary.in_macro.is_some() {
+    stack.push_str(&paint("M", DIM, color));
+  }
+
+  // A drop at the end of a scope which runs a `Drop` impl, rather than
+  // only the drop glue.
+  if let Some(ImplicitDrop::Custom) = boundary.implicit_drop {
+    stack.push_str(&paint("D", DIM, color));
+  }
+
+  if stack.is_empty() {
+    return stack;
+  }
+
+  format!(
+    "{}{stack}{}",
+    paint("[", DIM, color),
+    paint("]", DIM, color)
+  )
+}
+
+type PermissionGetter = fn(&Permissions) -> bool;
+
+fn permission_letters(
+  permissions: Permissions,
+) -> Vec<(&'static str, PermissionGetter)> {
+  let all: [(&'static str, PermissionGetter); 4] = [
+    ("R", |p| p.read),
+    ("W", |p| p.write),
+    ("O", |p| p.drop),
+    ("I", |p| p.interior_write),
+  ];
+  all
+    .into_iter()
+    .filter(|(_, has)| has(&permissions))
+    .collect()
+}
+
+/// Render the rows of all step tables shown at a line.
+fn render_steps(display: &PermissionsLineDisplay, color: bool) -> Vec<String> {
+  display
+    .state
+    .iter()
+    .flat_map(|table| table.state.iter())
+    .map(|(place, diff)| render_diff(place, diff, color))
+    .collect()
+}
+
+fn render_diff(place: &str, diff: &PermissionsDataDiff, color: bool) -> String {
+  let perms = &diff.permissions;
+  let steps = [("R", perms.read), ("W", perms.write), ("O", perms.drop)]
+    .into_iter()
+    .map(|(letter, step)| match step {
+      ValueStep::High { .. } => paint(&format!("+{letter}"), GREEN, color),
+      ValueStep::Low => paint(&format!("-{letter}"), RED, color),
+      ValueStep::None { value: Some(true) } => format!(" {letter}"),
+      ValueStep::None { .. } => paint(" ‒", DIM, color),
+    })
+    .collect::<Vec<_>>()
+    .join(" ");
+  format!("{place}: {steps}")
+}
+
+/// The number of characters in `s` which are visible on a terminal.
+fn visible_width(s: &str) -> usize {
+  let mut width = 0;
+  let mut in_escape = false;
+  for c in s.chars() {
+    match c {
+      '\x1b' => in_escape = true,
+      'm' if in_escape => in_escape = false,
+      _ if in_escape => {}
+      _ => width += 1,
+    }
+  }
+  width
+}
+
+#[cfg(test)]
+mod test {
+  use aquascope::analysis::stepper::PermissionsDiff;
+
+  use super::*;
+
+  fn step(b: bool) -> ValueStep<bool> {
+    ValueStep::None { value: Some(b) }
+  }
+
+  #[test]
+  fn test_visible_width() {
+    assert_eq!(visible_width("abc"), 3);
+    assert_eq!(visible_width(&paint("RW", RED, true)), 2);
+    assert_eq!(visible_width(&paint("‒", DIM, true)), 1);
+  }
+
+  #[test]
+  fn test_render_diff() {
+    let permissions = PermissionsDiff {
+      read: step(true),
+      write: ValueStep::Low,
+      drop: ValueStep::High { value: true },
+    };
+    let diff = PermissionsDataDiff {
+      is_live: step(true),
+      type_droppable: step(true),
+      type_writeable: step(true),
+      path_moved: ValueStep::None { value: None },
+      path_uninitialized: step(false),
+      loan_read_refined: ValueStep::None { value: None },
+      loan_write_refined: ValueStep::None { value: None },
+      loan_drop_refined: ValueStep::None { value: None },
+      permissions,
+    };
+    assert_eq!(render_diff("v", &diff, false), "v:  R -W +O");
+  }
+}
@@ -0,0 +1,302 @@
+//! Selecting which bodies of a crate should be analyzed.
+//!
+//! By default Aquascope analyzes every body in the crate, which is
+//! fine for the small, single-file programs of the playground but
+//! wasteful (and noisy) on a real crate. A [`BodySelector`] narrows
+//! the set of analyzed bodies by function path, by a file and line
+//! range, or by a byte offset or position in a file (useful for editors).
+
+use std::{
+  fmt,
+  path::{Path, PathBuf},
+  str::FromStr,
+};
+
+use clap::Args;
+use rustc_hir::BodyId;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::{source_map::SourceMap, Span};
+use rustc_utils::source_map::range::CharPos;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Args, Serialize, Deserialize)]
+pub struct BodySelector {
+  /// Only analyze the bodies of these functions, e.g. `my_mod::foo`.
+  /// A path matches any item whose full path ends with the given segments.
+  #[clap(long = "function")]
+  pub functions: Vec<String>,
+
+  /// Only analyze bodies defined in this file.
+  #[clap(long)]
+  pub file: Option<PathBuf>,
+
+  /// Only analyze bodies overlapping this line range (1-based, inclusive)
+  /// of `--file`, written as `START-END` or `LINE`.
+  #[clap(long, requires = "file")]
+  pub lines: Option<LineRange>,
+
+  /// Only analyze the innermost body containing this byte offset of `--file`.
+  #[clap(long, requires = "file")]
+  pub offset: Option<usize>,
+
+  /// Only analyze the innermost body containing this position of `--file`,
+  /// written as `LINE:COLUMN` (1-based, the column counts characters).
+  #[clap(long, requires = "file")]
+  pub position: Option<SourcePosition>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineRange {
+  pub start: usize,
+  pub end: usize,
+}
+
+impl FromStr for LineRange {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let parse = |n: &str| {
+      n.trim()
+        .parse::<usize>()
+        .map_err(|_| format!("Could not parse line number: {n}"))
+    };
+    let (start, end) = match s.split_once('-') {
+      Some((start, end)) => (parse(start)?, parse(end)?),
+      None => {
+        let line = parse(s)?;
+        (line, line)
+      }
+    };
+    if start == 0 || end < start {
+      return Err(format!("Invalid line range: {s}"));
+    }
+    Ok(LineRange { start, end })
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourcePosition {
+  pub line: usize,
+  pub column: usize,
+}
+
+impl FromStr for SourcePosition {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let invalid = || format!("Invalid position, expected LINE:COLUMN: {s}");
+    let (line, column) = s.split_once(':').ok_or_else(invalid)?;
+    let parse = |n: &str| n.trim().parse::<usize>().ok().filter(|n| *n > 0);
+    match (parse(line), parse(column)) {
+      (Some(line), Some(column)) => Ok(SourcePosition { line, column }),
+      _ => Err(invalid()),
+    }
+  }
+}
+
+impl From<SourcePosition> for CharPos {
+  fn from(pos: SourcePosition) -> Self {
+    CharPos {
+      line: pos.line - 1,
+      column: pos.column - 1,
+    }
+  }
+}
+
+impl fmt::Display for BodySelector {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut parts = Vec::new();
+    if !self.functions.is_empty() {
+      parts.push(format!("function {}", self.functions.join(", ")));
+    }
+    if let Some(file) = &self.file {
+      parts.push(format!("file {}", file.display()));
+    }
+    if let Some(LineRange { start, end }) = self.lines {
+      parts.push(format!("lines {start}-{end}"));
+    }
+    if let Some(offset) = self.offset {
+      parts.push(format!("offset {offset}"));
+    }
+    if let Some(SourcePosition { line, column }) = self.position {
+      parts.push(format!("position {line}:{column}"));
+    }
+    write!(f, "{}", parts.join(", "))
+  }
+}
+
+impl BodySelector {
+  /// Returns true if the selector accepts every body.
+  pub fn is_empty(&self) -> bool {
+    self.functions.is_empty()
+      && self.file.is_none()
+      && self.lines.is_none()
+      && self.offset.is_none()
+      && self.position.is_none()
+  }
+
+  /// Is the `--file` of the selector a source file of the crate?
+  pub fn targets_crate(&self, tcx: TyCtxt) -> bool {
+    let Some(wanted) = &self.file else {
+      return false;
+    };
+
+    let source_map = tcx.sess.source_map();
+    let files = source_map.files();
+    files.iter().filter(|file| !file.is_imported()).any(|file| {
+      let actual = file.name.prefer_local().to_string();
+      file_matches(Path::new(&actual), wanted)
+    })
+  }
+
+  /// Filter `bodies` down to those matched by every provided criterion.
+  pub fn select(
+    &self,
+    tcx: TyCtxt,
+    bodies: Vec<(Span, BodyId)>,
+  ) -> Vec<(Span, BodyId)> {
+    if self.is_empty() {
+      return bodies;
+    }
+
+    let source_map = tcx.sess.source_map();
+    let mut selected = bodies
+      .into_iter()
+      .filter(|(span, body_id)| {
+        self.matches_function(tcx, *body_id)
+          && self.matches_file(source_map, *span)
+          && self.matches_lines(source_map, *span)
+          && self.contains_offset(source_map, *span)
+          && self.contains_position(source_map, *span)
+      })
+      .collect::<Vec<_>>();
+
+    // Bodies nest (e.g. closures within functions), for an offset we
+    // only want the innermost one, i.e. the body "under the cursor".
+    if self.offset.is_some() || self.position.is_some() {
+      selected.sort_by_key(|(span, _)| span.hi() - span.lo());
+      selected.truncate(1);
+    }
+
+    selected
+  }
+
+  fn matches_function(&self, tcx: TyCtxt, body_id: BodyId) -> bool {
+    if self.functions.is_empty() {
+      return true;
+    }
+
+    let def_id = tcx.hir().body_owner_def_id(body_id);
+    let def_path = tcx.def_path_str(def_id.to_def_id());
+    self
+      .functions
+      .iter()
+      .any(|wanted| path_ends_with(&def_path, wanted))
+  }
+
+  fn matches_file(&self, source_map: &SourceMap, span: Span) -> bool {
+    let Some(wanted) = &self.file else {
+      return true;
+    };
+
+    let filename = source_map.span_to_filename(span);
+    let actual = filename.prefer_local().to_string();
+    file_matches(Path::new(&actual), wanted)
+  }
+
+  fn matches_lines(&self, source_map: &SourceMap, span: Span) -> bool {
+    let Some(LineRange { start, end }) = self.lines else {
+      return true;
+    };
+
+    let lo = source_map.lookup_char_pos(span.lo()).line;
+    let hi = source_map.lookup_char_pos(span.hi()).line;
+    lo <= end && start <= hi
+  }
+
+  fn contains_offset(&self, source_map: &SourceMap, span: Span) -> bool {
+    let Some(offset) = self.offset else {
+      return true;
+    };
+
+    let lo = source_map.lookup_byte_offset(span.lo()).pos.0 as usize;
+    let hi = source_map.lookup_byte_offset(span.hi()).pos.0 as usize;
+    lo <= offset && offset <= hi
+  }
+
+  fn contains_position(&self, source_map: &SourceMap, span: Span) -> bool {
+    let Some(SourcePosition { line, column }) = self.position else {
+      return true;
+    };
+
+    // `Loc` lines are 1-based while its columns are 0-based.
+    let position = (line, column - 1);
+    let lo = source_map.lookup_char_pos(span.lo());
+    let hi = source_map.lookup_char_pos(span.hi());
+    (lo.line, lo.col.0) <= position && position <= (hi.line, hi.col.0)
+  }
+}
+
+/// Does the item path `def_path` end with the segments of `wanted`?
+///
+/// Paths are compared segment-wise so that `foo` does not match `bar::my_foo`.
+fn path_ends_with(def_path: &str, wanted: &str) -> bool {
+  let wanted = wanted.trim_start_matches("crate::");
+  let actual = def_path.split("::").collect::<Vec<_>>();
+  let wanted = wanted.split("::").collect::<Vec<_>>();
+  actual.ends_with(&wanted)
+}
+
+fn file_matches(actual: &Path, wanted: &Path) -> bool {
+  if actual.ends_with(wanted) {
+    return true;
+  }
+
+  // Fall back to comparing absolute paths, the user may have provided
+  // an absolute path while rustc reports paths relative to the package.
+  match (actual.canonicalize(), wanted.canonicalize()) {
+    (Ok(actual), Ok(wanted)) => actual == wanted,
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_parse_line_range() {
+    assert_eq!(
+      "10-20".parse::<LineRange>(),
+      Ok(LineRange { start: 10, end: 20 })
+    );
+    assert_eq!("7".parse::<LineRange>(), Ok(LineRange { start: 7, end: 7 }));
+    assert!("20-10".parse::<LineRange>().is_err());
+    assert!("0-3".parse::<LineRange>().is_err());
+    assert!("a-b".parse::<LineRange>().is_err());
+  }
+
+  #[test]
+  fn test_parse_position() {
+    assert_eq!(
+      "12:5".parse::<SourcePosition>(),
+      Ok(SourcePosition {
+        line: 12,
+        column: 5
+      })
+    );
+    assert!("12".parse::<SourcePosition>().is_err());
+    assert!("0:5".parse::<SourcePosition>().is_err());
+  }
+
+  #[test]
+  fn test_path_ends_with() {
+    assert!(path_ends_with("my_mod::foo", "foo"));
+    assert!(path_ends_with("my_mod::foo", "my_mod::foo"));
+    assert!(path_ends_with("my_mod::foo", "crate::my_mod::foo"));
+    assert!(path_ends_with(
+      "my_mod::foo::{closure#0}",
+      "foo::{closure#0}"
+    ));
+    assert!(!path_ends_with("my_mod::my_foo", "foo"));
+    assert!(!path_ends_with("foo", "my_mod::foo"));
+  }
+}
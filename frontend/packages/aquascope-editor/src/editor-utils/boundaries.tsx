@@ -11,16 +11,21 @@ import type {
 } from "../types.js";
 import {
   type PermLetter,
+  customDropChar,
   flowChar,
   hideLoanRegion,
   hideMoveRegion,
+  inMacroChar,
+  interiorChar,
   linecolToPosition,
   makeDecorationField,
+  operatorChar,
   ownChar,
   permName,
   readChar,
   showLoanRegion,
   showMoveRegion,
+  uncheckedChar,
   writeChar
 } from "./misc.js";
 
@@ -104,6 +109,20 @@ let PermStack = ({
         hideMoveRegion(facts, data.path_moved, ["own"]);
       }
     },
+    {
+      content: interiorChar,
+      names: ["perm", "interior"],
+      exp: boundary.expected.interior_write,
+      act: boundary.actual.interior_write,
+      showit: () => {
+        showLoanRegion(facts, data.loan_read_refined, ["interior"]);
+        showMoveRegion(facts, data.path_moved, ["interior"]);
+      },
+      hideit: () => {
+        hideLoanRegion(facts, data.loan_read_refined, ["interior"]);
+        hideMoveRegion(facts, data.path_moved, ["interior"]);
+      }
+    },
     {
       content: flowChar,
       names: ["perm", "flow"],
@@ -111,6 +130,38 @@ let PermStack = ({
       act: !(boundary.expecting_flow?.is_violation ?? false),
       showit: () => void null,
       hideit: () => void null
+    },
+    {
+      content: uncheckedChar,
+      names: ["perm", "unchecked"],
+      exp: boundary.unchecked !== undefined,
+      act: !(boundary.unchecked?.aliases.some(a => a.is_violation) ?? false),
+      showit: () => void null,
+      hideit: () => void null
+    },
+    {
+      content: operatorChar,
+      names: ["perm", "operator"],
+      exp: boundary.operator !== undefined,
+      act: true,
+      showit: () => void null,
+      hideit: () => void null
+    },
+    {
+      content: inMacroChar,
+      names: ["perm", "in-macro"],
+      exp: boundary.in_macro !== undefined,
+      act: true,
+      showit: () => void null,
+      hideit: () => void null
+    },
+    {
+      content: customDropChar,
+      names: ["perm", "custom-drop"],
+      exp: boundary.implicit_drop === "Custom",
+      act: boundary.actual.drop,
+      showit: () => void null,
+      hideit: () => void null
     }
   ];
 
@@ -154,7 +205,12 @@ class BoundaryPointWidget extends WidgetType {
       toi(this.boundary.expected.read),
       toi(this.boundary.expected.write),
       toi(this.boundary.expected.drop),
-      toi(this.boundary.expecting_flow !== undefined)
+      toi(this.boundary.expected.interior_write),
+      toi(this.boundary.expecting_flow !== undefined),
+      toi(this.boundary.unchecked !== undefined),
+      toi(this.boundary.operator !== undefined),
+      toi(this.boundary.in_macro !== undefined),
+      toi(this.boundary.implicit_drop === "Custom")
     ].reduce((a, b) => a + b, 0);
     this.line = view.state.doc.lineAt(
       linecolToPosition(boundary.location, view.state.doc)
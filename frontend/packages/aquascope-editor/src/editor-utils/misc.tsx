@@ -26,10 +26,20 @@ export const readChar = "R";
 export const writeChar = "W";
 export const flowChar = "F";
 export const ownChar = "O";
+export const interiorChar = "I";
+export const uncheckedChar = "U";
+export const customDropChar = "D";
+export const operatorChar = "&";
+export const inMacroChar = "M";
 export type PermLetter =
   | typeof readChar
   | typeof writeChar
   | typeof ownChar
+  | typeof interiorChar
+  | typeof uncheckedChar
+  | typeof customDropChar
+  | typeof operatorChar
+  | typeof inMacroChar
   | typeof flowChar;
 
 // ----------
@@ -51,6 +61,16 @@ export let permName = (c: PermLetter): string => {
     return "write";
   } else if (c === "O") {
     return "own";
+  } else if (c === "I") {
+    return "interior write";
+  } else if (c === "U") {
+    return "unchecked";
+  } else if (c === "D") {
+    return "custom drop";
+  } else if (c === "&") {
+    return "implicit borrow";
+  } else if (c === "M") {
+    return "in macro";
   } else {
     return "flow";
   }
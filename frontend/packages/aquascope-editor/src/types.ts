@@ -10,7 +10,13 @@ export { Permissions } from "./bindings/Permissions";
 export { PermissionsBoundary } from "./bindings/PermissionsBoundary";
 
 export { AquascopeError } from "./bindings/AquascopeError";
+export { AnalysisStage } from "./bindings/AnalysisStage";
 export { AnalysisOutput } from "./bindings/AnalysisOutput";
+export { BodyMetadata } from "./bindings/BodyMetadata";
+export { BodyKind } from "./bindings/BodyKind";
+export { FileTable } from "./bindings/FileTable";
+export { Diagnostic } from "./bindings/Diagnostic";
+export { DiagnosticLabel } from "./bindings/DiagnosticLabel";
 export { ValueStep } from "./bindings/ValueStep";
 
 export { LoanKey } from "./bindings/LoanKey";
@@ -23,11 +29,30 @@ export { MoveRegions } from "./bindings/MoveRegions";
 
 export { Refiner } from "./bindings/Refiner";
 export { RefinementRegion } from "./bindings/RefinementRegion";
+export { PermissionsQuery } from "./bindings/PermissionsQuery";
+export { RefinerPoint } from "./bindings/RefinerPoint";
+export { LoanExplanation } from "./bindings/LoanExplanation";
+export { ExplanationVar } from "./bindings/ExplanationVar";
+export { LoanFlow } from "./bindings/LoanFlow";
 
 export { PermissionsLineDisplay } from "./bindings/PermissionsLineDisplay";
 export { PermissionsStepTable } from "./bindings/PermissionsStepTable";
 export { PermissionsDataDiff } from "./bindings/PermissionsDataDiff";
 export { PermissionsDiff } from "./bindings/PermissionsDiff";
+export { RegionGraph } from "./bindings/RegionGraph";
+export { RegionNode } from "./bindings/RegionNode";
+export { RegionFlowEdge } from "./bindings/RegionFlowEdge";
+export { FlowEdgeKind } from "./bindings/FlowEdgeKind";
+export { ScopeViolation } from "./bindings/ScopeViolation";
+export { UncheckedAccess } from "./bindings/UncheckedAccess";
+export { AliasedPlace } from "./bindings/AliasedPlace";
+export { ImplicitDrop } from "./bindings/ImplicitDrop";
+export { OverloadedOperator } from "./bindings/OverloadedOperator";
+export { Desugaring } from "./bindings/Desugaring";
+
+export { PermissionsTimeline } from "./bindings/PermissionsTimeline";
+export { TimelineStep } from "./bindings/TimelineStep";
+export { TimelineEntry } from "./bindings/TimelineEntry";
 
 export { MMemorySegment } from "./bindings/MMemorySegment";
 export { MPathSegment } from "./bindings/MPathSegment";
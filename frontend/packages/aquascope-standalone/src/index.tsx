@@ -85,6 +85,21 @@ window.addEventListener("load", () => {
           changeTab(tabs[1]);
           analysisErrorCard.classList.add("live");
           analysisErrorMsg.innerText = err.msg;
+        } else if (
+          err.type === "UnsupportedFeature" ||
+          err.type === "StepperError"
+        ) {
+          changeTab(tabs[1]);
+          analysisErrorCard.classList.add("live");
+          analysisErrorMsg.innerText = err.msg;
+        } else if (err.type === "Timeout") {
+          changeTab(tabs[1]);
+          analysisErrorCard.classList.add("live");
+          analysisErrorMsg.innerText = `The analysis exceeded its time limit of ${err.limit_secs}s`;
+        } else if (err.type === "AnalysisPanic") {
+          changeTab(tabs[1]);
+          analysisErrorCard.classList.add("live");
+          analysisErrorMsg.innerText = `${err.body} (${err.stage}): ${err.msg}`;
         } else {
           console.error("an unknown error occurred:", err);
         }